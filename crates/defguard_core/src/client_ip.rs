@@ -0,0 +1,157 @@
+//! Client IP resolution that only trusts `X-Forwarded-For` when the request
+//! actually came through one of the configured trusted proxies.
+//!
+//! Blindly trusting `InsecureClientIp` (as the name suggests) lets anyone
+//! spoof their source IP by sending an `X-Forwarded-For` header directly,
+//! which defeats IP-based rate limiting and audit logging alike. Instead we
+//! look at the real peer address from the TCP connection and only fall back
+//! to the forwarded header chain when that peer is a proxy we've been told
+//! to trust.
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{connect_info::ConnectInfo, FromRequestParts};
+use http::request::Parts;
+use ipnetwork::IpNetwork;
+
+use crate::{error::WebError, server_config};
+
+/// Forwarded-chain headers we'll look at, most-specific first. `Forwarded`
+/// (RFC 7239) and `X-Real-IP` are only consulted when `X-Forwarded-For` is
+/// absent, so a proxy that sets several doesn't get its chain double-walked.
+const FORWARDED_HEADERS: &[&str] = &["x-forwarded-for", "forwarded", "x-real-ip"];
+
+fn is_trusted_proxy(ip: IpAddr, trusted_proxies: &[IpNetwork]) -> bool {
+    trusted_proxies.iter().any(|network| network.contains(ip))
+}
+
+/// Extracts the address out of a single forwarded-chain hop.
+///
+/// Handles both the bare-IP hops used by `X-Forwarded-For`/`X-Real-IP` and
+/// RFC 7239 `Forwarded` hops, where the address is carried in a `for=`
+/// directive alongside others (`for=192.0.2.60;proto=http;by=203.0.113.43`)
+/// and may be quoted and/or bracketed with a port
+/// (`for="[2001:db8::1]:4711"`).
+fn parse_hop_ip(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim();
+    let value = if hop.contains(';') || hop.contains('=') {
+        hop.split(';').find_map(|directive| {
+            let directive = directive.trim();
+            (directive.len() >= 4 && directive[..4].eq_ignore_ascii_case("for="))
+                .then(|| &directive[4..])
+        })?
+    } else {
+        hop
+    };
+
+    let value = value.trim().trim_matches('"');
+    let value = match value.strip_prefix('[') {
+        // Bracketed IPv6 literal, optionally followed by `:<port>`.
+        Some(rest) => rest.split(']').next().unwrap_or(rest),
+        // Bare `ip:port` (IPv4) rather than a bracketed IPv6 literal -
+        // an IPv6 address without brackets has more than one colon.
+        None if value.matches(':').count() == 1 => {
+            value.split(':').next().unwrap_or(value)
+        }
+        None => value,
+    };
+    value.parse().ok()
+}
+
+/// Walks a forwarded-for style header from right to left, skipping over any
+/// hop that is itself a trusted proxy, and returns the first (rightmost)
+/// address that isn't. That's the closest hop we can't vouch for, i.e. the
+/// real client.
+///
+/// At most `trusted_proxies.len()` hops are skipped - a request can never be
+/// trusted through more hops than we have trusted proxies configured for, so
+/// a forged header with an arbitrarily long hop chain can't be walked past
+/// our actual proxy depth.
+fn resolve_forwarded_for(header: &str, trusted_proxies: &[IpNetwork]) -> Option<IpAddr> {
+    let max_hops = trusted_proxies.len();
+    header
+        .split(',')
+        .rev()
+        .filter_map(parse_hop_ip)
+        .take(max_hops + 1)
+        .find(|ip| !is_trusted_proxy(*ip, trusted_proxies))
+}
+
+/// Resolves the real client IP for the current request, honoring the
+/// `X-Forwarded-For` chain only when the direct peer is a configured
+/// trusted proxy.
+pub(crate) async fn resolve_client_ip<S: Send + Sync>(
+    parts: &mut Parts,
+    state: &S,
+) -> Result<IpAddr, WebError> {
+    let ConnectInfo(peer_addr) = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+        .await
+        .map_err(|_| WebError::ClientIpError)?;
+    let peer_ip = peer_addr.ip();
+
+    let trusted_proxies = &server_config().trusted_proxies;
+    if trusted_proxies.is_empty() || !is_trusted_proxy(peer_ip, trusted_proxies) {
+        return Ok(peer_ip);
+    }
+
+    for header_name in FORWARDED_HEADERS {
+        if let Some(value) = parts.headers.get(*header_name) {
+            let header = value.to_str().map_err(|_| WebError::ClientIpError)?;
+            return Ok(resolve_forwarded_for(header, trusted_proxies).unwrap_or(peer_ip));
+        }
+    }
+
+    Ok(peer_ip)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_forwarded_for_skips_trusted_hops() {
+        let trusted = vec!["10.0.0.0/8".parse().unwrap()];
+        let header = "203.0.113.7, 10.0.0.1";
+        assert_eq!(
+            resolve_forwarded_for(header, &trusted),
+            Some("203.0.113.7".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_resolve_forwarded_for_all_trusted() {
+        let trusted = vec!["10.0.0.0/8".parse().unwrap()];
+        let header = "10.0.0.2, 10.0.0.1";
+        assert_eq!(resolve_forwarded_for(header, &trusted), None);
+    }
+
+    #[test]
+    fn test_resolve_forwarded_with_extra_directives() {
+        let trusted = vec!["10.0.0.0/8".parse().unwrap()];
+        let header = "for=203.0.113.7;proto=http;by=203.0.113.43, for=10.0.0.1";
+        assert_eq!(
+            resolve_forwarded_for(header, &trusted),
+            Some("203.0.113.7".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_resolve_forwarded_with_quoted_bracketed_ipv6_and_port() {
+        let trusted = vec!["10.0.0.0/8".parse().unwrap()];
+        let header = "for=\"[2001:db8::1]:4711\", for=10.0.0.1";
+        assert_eq!(
+            resolve_forwarded_for(header, &trusted),
+            Some("2001:db8::1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_resolve_forwarded_with_ipv4_and_port() {
+        let trusted = vec!["10.0.0.0/8".parse().unwrap()];
+        let header = "for=192.0.2.43:47011, for=10.0.0.1";
+        assert_eq!(
+            resolve_forwarded_for(header, &trusted),
+            Some("192.0.2.43".parse().unwrap())
+        );
+    }
+}
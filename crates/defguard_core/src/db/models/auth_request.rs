@@ -0,0 +1,92 @@
+use chrono::{NaiveDateTime, Utc};
+use model_derive::Model;
+use sqlx::{query_as, Error as SqlxError, FromRow, PgExecutor};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::device::DeviceType;
+use crate::db::{Id, NoId};
+
+/// A pending device-add request, parked until an admin approves or rejects
+/// it. Mirrors the same "device wants to join, a human has to say yes"
+/// pattern used for login approvals, but for provisioning a new WireGuard
+/// peer instead of starting a session.
+#[derive(Clone, Debug, Deserialize, FromRow, Model, Serialize, ToSchema)]
+pub struct AuthRequest<I = NoId> {
+    pub id: I,
+    #[model(ref)]
+    pub uuid: Uuid,
+    pub user_id: Id,
+    pub device_name: String,
+    pub wireguard_pubkey: String,
+    pub request_ip: String,
+    #[model(enum)]
+    pub device_type: DeviceType,
+    pub creation_date: NaiveDateTime,
+    pub approved: Option<bool>,
+    pub response_date: Option<NaiveDateTime>,
+}
+
+impl AuthRequest {
+    #[must_use]
+    pub fn new(
+        user_id: Id,
+        device_name: String,
+        wireguard_pubkey: String,
+        request_ip: String,
+        device_type: DeviceType,
+    ) -> Self {
+        Self {
+            id: NoId,
+            uuid: Uuid::new_v4(),
+            user_id,
+            device_name,
+            wireguard_pubkey,
+            request_ip,
+            device_type,
+            creation_date: Utc::now().naive_utc(),
+            approved: None,
+            response_date: None,
+        }
+    }
+}
+
+impl AuthRequest<Id> {
+    pub(crate) async fn find_by_uuid<'e, E: PgExecutor<'e>>(
+        executor: E,
+        uuid: Uuid,
+    ) -> Result<Option<Self>, SqlxError> {
+        query_as!(
+            Self,
+            "SELECT id, uuid, user_id, device_name, wireguard_pubkey, request_ip, \
+            device_type \"device_type: DeviceType\", creation_date, approved, response_date \
+            FROM auth_request WHERE uuid = $1",
+            uuid
+        )
+        .fetch_optional(executor)
+        .await
+    }
+
+    /// Returns all requests still awaiting a decision, oldest first.
+    pub(crate) async fn all_pending<'e, E: PgExecutor<'e>>(
+        executor: E,
+    ) -> Result<Vec<Self>, SqlxError> {
+        query_as!(
+            Self,
+            "SELECT id, uuid, user_id, device_name, wireguard_pubkey, request_ip, \
+            device_type \"device_type: DeviceType\", creation_date, approved, response_date \
+            FROM auth_request WHERE approved IS NULL ORDER BY creation_date",
+        )
+        .fetch_all(executor)
+        .await
+    }
+
+    pub(crate) fn is_pending(&self) -> bool {
+        self.approved.is_none()
+    }
+
+    pub(crate) fn mark_decided(&mut self, approved: bool) {
+        self.approved = Some(approved);
+        self.response_date = Some(Utc::now().naive_utc());
+    }
+}
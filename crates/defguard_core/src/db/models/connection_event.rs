@@ -0,0 +1,214 @@
+use chrono::{NaiveDateTime, Utc};
+use model_derive::Model;
+use sqlx::{query, query_as, Error as SqlxError, FromRow, PgConnection, PgExecutor, PgPool};
+use utoipa::ToSchema;
+
+use crate::db::{Id, NoId};
+
+/// How many recent events the ring buffer in [`ConnectionEvent::append`]
+/// keeps per device/network pair - enough to compute a meaningful rolling
+/// success rate without history growing unbounded for a flapping peer.
+pub(crate) const HISTORY_LIMIT: i64 = 50;
+
+/// Why a connection attempt failed, recorded alongside a `false` outcome in
+/// [`ConnectionEvent`]. Borrows the shape of Fuchsia's saved-network
+/// manager `ConnectFailure`, so admins get an actionable reason instead of a
+/// bare boolean.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    /// No handshake completed before the peer was considered unreachable.
+    HandshakeTimeout,
+    /// The device's MFA challenge was rejected or timed out.
+    MfaRejected,
+    /// No free IP address was available to (re)assign.
+    IpExhausted,
+    /// Some other, unclassified failure.
+    Other,
+}
+
+/// One connection attempt's outcome for a device/network relation. Rows are
+/// kept as a bounded ring buffer (see [`HISTORY_LIMIT`]) of the most recent
+/// entries per relation - mirrors Fuchsia's `PastConnectionData`.
+#[derive(Clone, Debug, Deserialize, FromRow, Model, Serialize, ToSchema)]
+pub struct ConnectionEvent<I = NoId> {
+    pub id: I,
+    pub device_id: Id,
+    pub wireguard_network_id: Id,
+    pub collected_at: NaiveDateTime,
+    pub success: bool,
+    #[model(enum)]
+    pub failure_reason: Option<FailureReason>,
+}
+
+impl ConnectionEvent {
+    #[must_use]
+    pub fn success(device_id: Id, wireguard_network_id: Id) -> Self {
+        Self {
+            id: NoId,
+            device_id,
+            wireguard_network_id,
+            collected_at: Utc::now().naive_utc(),
+            success: true,
+            failure_reason: None,
+        }
+    }
+
+    #[must_use]
+    pub fn failure(device_id: Id, wireguard_network_id: Id, reason: FailureReason) -> Self {
+        Self {
+            id: NoId,
+            device_id,
+            wireguard_network_id,
+            collected_at: Utc::now().naive_utc(),
+            success: false,
+            failure_reason: Some(reason),
+        }
+    }
+}
+
+impl ConnectionEvent<Id> {
+    /// Appends `event` to history, then trims its device/network pair's
+    /// ring buffer back down to [`HISTORY_LIMIT`] most recent entries.
+    pub(crate) async fn append(
+        transaction: &mut PgConnection,
+        event: ConnectionEvent,
+    ) -> Result<(), SqlxError> {
+        let device_id = event.device_id;
+        let wireguard_network_id = event.wireguard_network_id;
+        event.save(&mut *transaction).await?;
+
+        query!(
+            "DELETE FROM connection_event WHERE id IN ( \
+                SELECT id FROM connection_event \
+                WHERE device_id = $1 AND wireguard_network_id = $2 \
+                ORDER BY collected_at DESC OFFSET $3 \
+            )",
+            device_id,
+            wireguard_network_id,
+            HISTORY_LIMIT,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The `limit` most recent events for a device/network pair, freshest
+    /// first.
+    pub(crate) async fn recent<'e, E>(
+        executor: E,
+        device_id: Id,
+        wireguard_network_id: Id,
+        limit: i64,
+    ) -> Result<Vec<Self>, SqlxError>
+    where
+        E: PgExecutor<'e>,
+    {
+        query_as!(
+            Self,
+            "SELECT id, device_id, wireguard_network_id, collected_at, success, \
+                failure_reason \"failure_reason: FailureReason\" \
+            FROM connection_event \
+            WHERE device_id = $1 AND wireguard_network_id = $2 \
+            ORDER BY collected_at DESC LIMIT $3",
+            device_id,
+            wireguard_network_id,
+            limit
+        )
+        .fetch_all(executor)
+        .await
+    }
+
+    /// Fraction of `history`'s events that succeeded, in `[0.0, 1.0]`.
+    /// `None` if `history` is empty - there's nothing yet to judge by.
+    #[must_use]
+    pub(crate) fn success_rate(history: &[Self]) -> Option<f64> {
+        if history.is_empty() {
+            return None;
+        }
+        let successes = history.iter().filter(|event| event.success).count();
+        Some(successes as f64 / history.len() as f64)
+    }
+
+    /// Device/network pairs whose last `sample_size` events have a success
+    /// rate at or below `threshold` - a worklist of repeatedly-failing peers
+    /// for an admin dashboard or alert. Pairs with fewer than `sample_size`
+    /// events on record are skipped as not yet conclusive.
+    pub(crate) async fn repeatedly_failing(
+        pool: &PgPool,
+        sample_size: i64,
+        threshold: f64,
+    ) -> Result<Vec<(Id, Id)>, SqlxError> {
+        let pairs = query!("SELECT DISTINCT device_id, wireguard_network_id FROM connection_event")
+            .fetch_all(pool)
+            .await?;
+
+        let mut failing = Vec::new();
+        for pair in pairs {
+            let history =
+                Self::recent(pool, pair.device_id, pair.wireguard_network_id, sample_size).await?;
+            if history.len() as i64 == sample_size {
+                if let Some(rate) = Self::success_rate(&history) {
+                    if rate <= threshold {
+                        failing.push((pair.device_id, pair.wireguard_network_id));
+                    }
+                }
+            }
+        }
+
+        Ok(failing)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use super::*;
+    use crate::db::setup_pool;
+
+    #[test]
+    fn test_success_rate_empty() {
+        assert_eq!(ConnectionEvent::success_rate(&[]), None);
+    }
+
+    #[test]
+    fn test_success_rate_mixed() {
+        let history = vec![
+            ConnectionEvent::success(1, 1),
+            ConnectionEvent::failure(1, 1, FailureReason::HandshakeTimeout),
+            ConnectionEvent::success(1, 1),
+            ConnectionEvent::failure(1, 1, FailureReason::Other),
+        ];
+        assert_eq!(ConnectionEvent::success_rate(&history), Some(0.5));
+    }
+
+    #[sqlx::test]
+    async fn test_repeatedly_failing(_: PgPoolOptions, options: PgConnectOptions) {
+        let pool = setup_pool(options).await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        // device/network pair (1, 1): 4 failures out of 5, below threshold.
+        for _ in 0..4 {
+            ConnectionEvent::append(&mut conn, ConnectionEvent::failure(1, 1, FailureReason::Other))
+                .await
+                .unwrap();
+        }
+        ConnectionEvent::append(&mut conn, ConnectionEvent::success(1, 1))
+            .await
+            .unwrap();
+
+        // device/network pair (2, 1): healthy, should not be surfaced.
+        for _ in 0..5 {
+            ConnectionEvent::append(&mut conn, ConnectionEvent::success(2, 1))
+                .await
+                .unwrap();
+        }
+
+        let failing = ConnectionEvent::repeatedly_failing(&pool, 5, 0.5)
+            .await
+            .unwrap();
+        assert_eq!(failing, vec![(1, 1)]);
+    }
+}
@@ -0,0 +1,90 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use model_derive::Model;
+use rand::distributions::{Alphanumeric, DistString};
+use serde_json::Value as JsonValue;
+use sqlx::{query_as, Error as SqlxError, FromRow, PgExecutor};
+use utoipa::ToSchema;
+
+use crate::{
+    db::{Id, NoId},
+    server_config,
+};
+
+const ACCESS_CODE_LENGTH: usize = 8;
+
+/// A device asking to join, parked until a trusted device or admin approves
+/// it from their own session — the "login with device" pattern, applied to
+/// provisioning a new WireGuard peer instead of starting a session.
+#[derive(Clone, Debug, Deserialize, FromRow, Model, Serialize, ToSchema)]
+pub struct DeviceAuthRequest<I = NoId> {
+    pub id: I,
+    pub wireguard_pubkey: String,
+    pub request_ip: String,
+    pub device_info: Option<String>,
+    #[model(ref)]
+    pub access_code: String,
+    pub creation_date: NaiveDateTime,
+    pub approved: Option<bool>,
+    pub device_id: Option<Id>,
+    /// The `Vec<DeviceConfig>` produced when the device was provisioned,
+    /// stashed here so the polling device can pick it up without the server
+    /// re-running (and re-assigning IPs via) network provisioning.
+    pub device_configs: Option<JsonValue>,
+}
+
+impl DeviceAuthRequest {
+    #[must_use]
+    pub fn new(wireguard_pubkey: String, request_ip: String, device_info: Option<String>) -> Self {
+        let access_code = Alphanumeric.sample_string(&mut rand::thread_rng(), ACCESS_CODE_LENGTH);
+
+        Self {
+            id: NoId,
+            wireguard_pubkey,
+            request_ip,
+            device_info,
+            access_code,
+            creation_date: Utc::now().naive_utc(),
+            approved: None,
+            device_id: None,
+            device_configs: None,
+        }
+    }
+}
+
+impl DeviceAuthRequest<Id> {
+    pub(crate) async fn find_by_access_code<'e, E: PgExecutor<'e>>(
+        executor: E,
+        access_code: &str,
+    ) -> Result<Option<Self>, SqlxError> {
+        query_as!(
+            Self,
+            "SELECT id, wireguard_pubkey, request_ip, device_info, access_code, \
+            creation_date, approved, device_id, device_configs \
+            FROM device_auth_request WHERE access_code = $1",
+            access_code
+        )
+        .fetch_optional(executor)
+        .await
+    }
+
+    /// Requests outlive their usefulness after `device_auth_request_ttl_secs`
+    /// (configurable, since "a human looks at their phone" timing varies).
+    pub(crate) fn is_expired(&self) -> bool {
+        let ttl = TimeDelta::seconds(server_config().device_auth_request_ttl_secs);
+        Utc::now().naive_utc() > self.creation_date + ttl
+    }
+
+    pub(crate) fn is_pending(&self) -> bool {
+        self.approved.is_none()
+    }
+
+    pub(crate) fn mark_approved(&mut self, device_id: Id, configs: &JsonValue) {
+        self.approved = Some(true);
+        self.device_id = Some(device_id);
+        self.device_configs = Some(configs.clone());
+    }
+
+    pub(crate) fn mark_rejected(&mut self) {
+        self.approved = Some(false);
+    }
+}
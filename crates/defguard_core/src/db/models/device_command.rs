@@ -0,0 +1,65 @@
+use chrono::{NaiveDateTime, Utc};
+use model_derive::Model;
+use sqlx::{query_as, Error as SqlxError, FromRow, PgExecutor};
+use utoipa::ToSchema;
+
+use crate::db::{Id, NoId};
+
+/// A remote action an admin can push to a live peer, instead of waiting for
+/// it to next poll its config.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceCommandKind {
+    /// Ask the peer to re-download and apply its WireGuard config.
+    ForceReconfigure,
+    /// Ask the peer to tear down its current WireGuard connection.
+    Disconnect,
+    /// Generate a fresh preshared key for the device/network relation and
+    /// push it to both peer endpoints.
+    RotatePresharedKey,
+}
+
+/// A pending remote command for a device, queued until the device's client
+/// drains it via [`DeviceCommand::drain_pending`]. Also translated into a
+/// [`crate::db::GatewayEvent`] so a connected gateway can act on it right
+/// away, without waiting for the client to poll.
+#[derive(Clone, Debug, Deserialize, FromRow, Model, Serialize, ToSchema)]
+pub struct DeviceCommand<I = NoId> {
+    pub id: I,
+    pub device_id: Id,
+    #[model(enum)]
+    pub kind: DeviceCommandKind,
+    pub created: NaiveDateTime,
+    pub acknowledged_at: Option<NaiveDateTime>,
+}
+
+impl DeviceCommand {
+    #[must_use]
+    pub fn new(device_id: Id, kind: DeviceCommandKind) -> Self {
+        Self {
+            id: NoId,
+            device_id,
+            kind,
+            created: Utc::now().naive_utc(),
+            acknowledged_at: None,
+        }
+    }
+}
+
+impl DeviceCommand<Id> {
+    /// Deletes and returns every command still pending for `device_id`. This
+    /// doubles as the acknowledgement: once drained, a command is gone.
+    pub(crate) async fn drain_pending<'e, E: PgExecutor<'e>>(
+        executor: E,
+        device_id: Id,
+    ) -> Result<Vec<Self>, SqlxError> {
+        query_as!(
+            Self,
+            "DELETE FROM device_command WHERE device_id = $1 AND acknowledged_at IS NULL \
+            RETURNING id, device_id, kind \"kind: DeviceCommandKind\", created, acknowledged_at",
+            device_id
+        )
+        .fetch_all(executor)
+        .await
+    }
+}
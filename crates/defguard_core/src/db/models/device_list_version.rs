@@ -0,0 +1,283 @@
+use std::net::IpAddr;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use model_derive::Model;
+use serde_json::Value as JsonValue;
+use sha2::Sha256;
+use sqlx::{query, query_as, Error as SqlxError, FromRow, PgConnection, PgExecutor};
+use utoipa::ToSchema;
+
+use crate::{
+    db::{Id, NoId},
+    server_config,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A single device's membership as it appears in a signed device list.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct DeviceListEntry {
+    pub device_id: Id,
+    pub wireguard_pubkey: String,
+    pub wireguard_ips: Vec<IpAddr>,
+}
+
+impl DeviceListEntry {
+    async fn all_for_network<'e, E: PgExecutor<'e>>(
+        executor: E,
+        network_id: Id,
+    ) -> Result<Vec<Self>, SqlxError> {
+        query_as!(
+            Self,
+            "SELECT wnd.device_id, d.wireguard_pubkey, \
+                wnd.wireguard_ips \"wireguard_ips: Vec<IpAddr>\" \
+            FROM wireguard_network_device wnd JOIN device d ON d.id = wnd.device_id \
+            WHERE wnd.wireguard_network_id = $1 ORDER BY wnd.device_id",
+            network_id
+        )
+        .fetch_all(executor)
+        .await
+    }
+}
+
+/// A tamper-evident, append-only snapshot of a network's device membership.
+///
+/// Every membership-mutating operation (`add_device`, `add_user_devices`,
+/// `sync_allowed_devices`, `handle_mapped_devices`) appends a new version
+/// instead of overwriting the previous one, so gateways and auditors can
+/// replay the chain and detect any out-of-band edit to network membership.
+#[derive(Clone, Debug, Deserialize, FromRow, Model, Serialize, ToSchema)]
+pub struct DeviceListVersion<I = NoId> {
+    pub id: I,
+    pub network_id: Id,
+    pub version: i64,
+    pub prev_version: Option<i64>,
+    pub devices: JsonValue,
+    pub created_at: NaiveDateTime,
+    pub signature: String,
+}
+
+impl DeviceListVersion {
+    /// Bytes that get signed: network, version, prev_version and the device
+    /// list, in a fixed order so the same membership always signs the same.
+    fn signing_payload(
+        network_id: Id,
+        version: i64,
+        prev_version: Option<i64>,
+        devices: &JsonValue,
+    ) -> Vec<u8> {
+        format!(
+            "{network_id}:{version}:{}:{devices}",
+            prev_version.map_or_else(|| "-".to_string(), |v| v.to_string())
+        )
+        .into_bytes()
+    }
+
+    fn sign(payload: &[u8]) -> String {
+        let secret = server_config().device_list_signing_secret.as_bytes();
+        let mut mac =
+            HmacSha256::new_from_slice(secret).expect("HMAC accepts a key of any length");
+        mac.update(payload);
+        BASE64_STANDARD.encode(mac.finalize().into_bytes())
+    }
+
+    /// Reads the current membership of `network_id` and appends it as a new,
+    /// signed version in the chain.
+    ///
+    /// The network row is locked with `FOR UPDATE` first, so two concurrent
+    /// appends for the same network (e.g. an admin bulk-delete racing a
+    /// gateway-triggered reconfigure) can't both read the same `latest`
+    /// version and insert a duplicate `version` number - mirrors the lock
+    /// added for the analogous `device_list_timestamp` race.
+    pub(crate) async fn append_new_version(
+        conn: &mut PgConnection,
+        network_id: Id,
+    ) -> Result<DeviceListVersion<Id>, SqlxError> {
+        query!(
+            "SELECT id FROM wireguard_network WHERE id = $1 FOR UPDATE",
+            network_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let entries = DeviceListEntry::all_for_network(&mut *conn, network_id).await?;
+        let devices =
+            serde_json::to_value(&entries).expect("device list is always serializable");
+        let prev = DeviceListVersion::<Id>::latest_for_network(&mut *conn, network_id).await?;
+        let prev_version = prev.as_ref().map(|v| v.version);
+        let version = prev_version.map_or(1, |v| v + 1);
+        let payload = Self::signing_payload(network_id, version, prev_version, &devices);
+        let signature = Self::sign(&payload);
+        let created_at = Utc::now().naive_utc();
+
+        let id = query!(
+            "INSERT INTO device_list_version (network_id, version, prev_version, devices, created_at, signature) \
+            VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+            network_id,
+            version,
+            prev_version,
+            devices,
+            created_at,
+            signature,
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .id;
+
+        Ok(DeviceListVersion {
+            id,
+            network_id,
+            version,
+            prev_version,
+            devices,
+            created_at,
+            signature,
+        })
+    }
+}
+
+impl DeviceListVersion<Id> {
+    pub(crate) async fn latest_for_network<'e, E: PgExecutor<'e>>(
+        executor: E,
+        network_id: Id,
+    ) -> Result<Option<Self>, SqlxError> {
+        query_as!(
+            Self,
+            "SELECT id, network_id, version, prev_version, devices, created_at, signature \
+            FROM device_list_version WHERE network_id = $1 ORDER BY version DESC LIMIT 1",
+            network_id
+        )
+        .fetch_optional(executor)
+        .await
+    }
+
+    async fn all_for_network<'e, E: PgExecutor<'e>>(
+        executor: E,
+        network_id: Id,
+    ) -> Result<Vec<Self>, SqlxError> {
+        query_as!(
+            Self,
+            "SELECT id, network_id, version, prev_version, devices, created_at, signature \
+            FROM device_list_version WHERE network_id = $1 ORDER BY version",
+            network_id
+        )
+        .fetch_all(executor)
+        .await
+    }
+
+    fn signature_valid(&self) -> bool {
+        let expected = DeviceListVersion::sign(&DeviceListVersion::signing_payload(
+            self.network_id,
+            self.version,
+            self.prev_version,
+            &self.devices,
+        ));
+        expected == self.signature
+    }
+
+    /// Walks the full chain for `network_id` and verifies that every
+    /// version's `prev_version` links to the one before it and that every
+    /// signature matches its recorded payload. Returns the first broken
+    /// version's number, if any.
+    pub(crate) async fn verify_chain<'e, E: PgExecutor<'e>>(
+        executor: E,
+        network_id: Id,
+    ) -> Result<Option<i64>, SqlxError> {
+        let versions = Self::all_for_network(executor, network_id).await?;
+
+        let mut expected_prev = None;
+        for version in &versions {
+            if version.prev_version != expected_prev || !version.signature_valid() {
+                return Ok(Some(version.version));
+            }
+            expected_prev = Some(version.version);
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use super::*;
+    use crate::db::{setup_pool, WireguardNetwork};
+
+    #[test]
+    fn test_signing_payload_is_deterministic() {
+        let devices = json!([{"device_id": 1, "wireguard_pubkey": "key", "wireguard_ips": ["10.0.0.2"]}]);
+        let a = DeviceListVersion::signing_payload(1, 2, Some(1), &devices);
+        let b = DeviceListVersion::signing_payload(1, 2, Some(1), &devices);
+        assert_eq!(a, b);
+        assert_eq!(a, b"1:2:1:[{\"device_id\":1,\"wireguard_ips\":[\"10.0.0.2\"],\"wireguard_pubkey\":\"key\"}]".to_vec());
+    }
+
+    #[test]
+    fn test_signing_payload_has_no_prev_version_marker() {
+        let devices = json!([]);
+        let payload = DeviceListVersion::signing_payload(1, 1, None, &devices);
+        assert_eq!(payload, b"1:1:-:[]".to_vec());
+    }
+
+    #[sqlx::test]
+    async fn test_verify_chain_detects_no_tampering(_: PgPoolOptions, options: PgConnectOptions) {
+        let pool = setup_pool(options).await;
+
+        let mut network = WireguardNetwork::default();
+        network.try_set_address("10.1.1.1/24").unwrap();
+        let network = network.save(&pool).await.unwrap();
+
+        let mut conn = pool.acquire().await.unwrap();
+        DeviceListVersion::append_new_version(&mut conn, network.id)
+            .await
+            .unwrap();
+        DeviceListVersion::append_new_version(&mut conn, network.id)
+            .await
+            .unwrap();
+
+        let broken_at = DeviceListVersion::verify_chain(&pool, network.id)
+            .await
+            .unwrap();
+        assert_eq!(broken_at, None);
+    }
+
+    #[sqlx::test]
+    async fn test_verify_chain_detects_tampered_signature(
+        _: PgPoolOptions,
+        options: PgConnectOptions,
+    ) {
+        let pool = setup_pool(options).await;
+
+        let mut network = WireguardNetwork::default();
+        network.try_set_address("10.1.1.1/24").unwrap();
+        let network = network.save(&pool).await.unwrap();
+
+        let mut conn = pool.acquire().await.unwrap();
+        DeviceListVersion::append_new_version(&mut conn, network.id)
+            .await
+            .unwrap();
+        let second = DeviceListVersion::append_new_version(&mut conn, network.id)
+            .await
+            .unwrap();
+
+        // Simulate an out-of-band edit: tweak the recorded device list
+        // without re-signing it.
+        query!(
+            "UPDATE device_list_version SET devices = $1 WHERE id = $2",
+            json!([{"device_id": 999, "wireguard_pubkey": "forged", "wireguard_ips": []}]),
+            second.id,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let broken_at = DeviceListVersion::verify_chain(&pool, network.id)
+            .await
+            .unwrap();
+        assert_eq!(broken_at, Some(second.version));
+    }
+}
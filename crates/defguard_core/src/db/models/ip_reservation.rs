@@ -0,0 +1,139 @@
+use std::net::IpAddr;
+
+use chrono::{NaiveDateTime, Utc};
+use ipnetwork::IpNetwork;
+use model_derive::Model;
+use sqlx::{query_as, Error as SqlxError, FromRow, PgConnection, PgExecutor};
+use utoipa::ToSchema;
+
+use crate::db::{Id, NoId};
+
+/// A persistent reservation against a network's CIDR, in the spirit of
+/// innernet's CIDR management: either a bare sub-range carved out for
+/// infrastructure that isn't a defguard device (gateways, printers, other
+/// non-defguard hosts), or a single address permanently leased to a named
+/// device. Consulted by [`super::device::Device::assign_next_network_ip`]
+/// so auto-assigned IPs never collide with either, and by
+/// [`super::device::Device::assign_network_ips`] to reject a manual
+/// assignment landing inside a reservation it doesn't own.
+#[derive(Clone, Debug, Deserialize, FromRow, Model, Serialize, ToSchema)]
+pub struct IpReservation<I = NoId> {
+    pub id: I,
+    pub wireguard_network_id: Id,
+    /// The reserved block. A permanent device lease uses a single-address
+    /// mask (e.g. a `/32`); an infrastructure carve-out may span a wider
+    /// range.
+    #[schema(value_type = String)]
+    pub cidr: IpNetwork,
+    /// Free-text description, e.g. "gateway", "office printer", or left to
+    /// echo the device name for a lease.
+    pub label: String,
+    /// `Some` for a permanent lease bound to one device; `None` for a bare
+    /// reserved range no device owns.
+    pub device_id: Option<Id>,
+    pub created: NaiveDateTime,
+}
+
+/// Upper bound on how many host addresses a linear, per-address scan (here
+/// and in [`super::device::Device::assign_next_network_ip`]) will walk
+/// before giving up. Any sanely-sized WireGuard subnet (an IPv4 `/12` or
+/// narrower, or an IPv6 `/108` or narrower) is within this bound; an
+/// unusually wide prefix is not, and without this cap a scan over it would
+/// hang/OOM the request instead of failing fast - at the cost of treating
+/// such a prefix as exhausted once this many addresses have been scanned,
+/// even if free ones remain further in.
+pub(crate) const MAX_SCANNABLE_HOSTS: usize = 1 << 20;
+
+impl IpReservation {
+    #[must_use]
+    pub fn new(
+        wireguard_network_id: Id,
+        cidr: IpNetwork,
+        label: String,
+        device_id: Option<Id>,
+    ) -> Self {
+        Self {
+            id: NoId,
+            wireguard_network_id,
+            cidr,
+            label,
+            device_id,
+            created: Utc::now().naive_utc(),
+        }
+    }
+}
+
+impl IpReservation<Id> {
+    /// Every reservation on `network_id`, both bare ranges and device leases.
+    pub(crate) async fn all_for_network<'e, E>(
+        executor: E,
+        network_id: Id,
+    ) -> Result<Vec<Self>, SqlxError>
+    where
+        E: PgExecutor<'e>,
+    {
+        query_as!(
+            Self,
+            "SELECT id, wireguard_network_id, cidr, label, device_id, created \
+            FROM ip_reservation WHERE wireguard_network_id = $1",
+            network_id
+        )
+        .fetch_all(executor)
+        .await
+    }
+
+    /// Whether `ip` falls inside this reservation's block.
+    #[must_use]
+    pub(crate) fn contains(&self, ip: &IpAddr) -> bool {
+        self.cidr.contains(*ip)
+    }
+
+    /// Whether `ip` is covered by a reservation in `reservations` that
+    /// `device_id` doesn't itself own - i.e. assigning `ip` to `device_id`
+    /// would collide with someone else's reserved block or lease.
+    #[must_use]
+    pub(crate) fn conflicts(reservations: &[Self], ip: &IpAddr, device_id: Id) -> bool {
+        reservations
+            .iter()
+            .any(|reservation| reservation.contains(ip) && reservation.device_id != Some(device_id))
+    }
+
+    /// Number of host addresses in `subnet` that are neither the
+    /// network/broadcast address, the subnet's own (gateway) address,
+    /// already assigned to a device, nor covered by a reservation.
+    ///
+    /// The scan stops after [`MAX_SCANNABLE_HOSTS`] addresses, so for an
+    /// IPv6 `subnet` wider than that the result is a lower bound on the
+    /// true free count rather than an exact one.
+    pub(crate) async fn free_ip_count(
+        transaction: &mut PgConnection,
+        network_id: Id,
+        subnet: &IpNetwork,
+    ) -> Result<usize, SqlxError> {
+        let reservations = Self::all_for_network(&mut *transaction, network_id).await?;
+        let assigned: Vec<IpAddr> = sqlx::query_scalar!(
+            "SELECT unnest(wireguard_ips) \"ip: IpAddr\" \
+            FROM wireguard_network_device WHERE wireguard_network_id = $1",
+            network_id
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        let gateway_address = subnet.ip();
+        let network_address = subnet.network();
+        let broadcast_address = subnet.broadcast();
+        let free = subnet
+            .into_iter()
+            .take(MAX_SCANNABLE_HOSTS)
+            .filter(|ip| {
+                *ip != gateway_address
+                    && *ip != network_address
+                    && *ip != broadcast_address
+                    && !assigned.contains(ip)
+                    && !reservations.iter().any(|reservation| reservation.contains(ip))
+            })
+            .count();
+
+        Ok(free)
+    }
+}
@@ -0,0 +1,94 @@
+use model_derive::Model;
+use sqlx::{query_as, Error as SqlxError, FromRow, PgExecutor, Type};
+use utoipa::ToSchema;
+
+use crate::db::{Id, NoId};
+
+/// A single capability that can be delegated for a specific network, instead
+/// of handing out the blanket `AdminRole`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, ToSchema, Type)]
+#[sqlx(type_name = "network_permission_kind", rename_all = "snake_case")]
+pub enum NetworkPermissionKind {
+    /// Create/modify/delete the network itself.
+    ManageNetwork,
+    /// Add, remove or reconfigure devices within the network.
+    ManageDevices,
+    /// Read-only access to connection/usage statistics.
+    ViewStats,
+}
+
+/// Grants `permission` on `network_id` to either a user or a group.
+/// Exactly one of `user_id`/`group_id` is set.
+#[derive(Clone, Debug, Deserialize, FromRow, Model, Serialize, ToSchema)]
+pub struct NetworkPermission<I = NoId> {
+    pub id: I,
+    pub network_id: Id,
+    pub user_id: Option<Id>,
+    pub group_id: Option<Id>,
+    #[model(enum)]
+    pub kind: NetworkPermissionKind,
+}
+
+impl NetworkPermission<Id> {
+    /// Returns every permission kind `user_id` holds on `network_id`, either
+    /// directly or through group membership.
+    pub(crate) async fn for_user_and_network<'e, E: PgExecutor<'e>>(
+        executor: E,
+        user_id: Id,
+        network_id: Id,
+    ) -> Result<Vec<NetworkPermissionKind>, SqlxError> {
+        query_as!(
+            NetworkPermissionRow,
+            "SELECT kind \"kind: NetworkPermissionKind\" FROM network_permission \
+            WHERE network_id = $1 AND (user_id = $2 OR group_id IN \
+            (SELECT group_id FROM \"group_user\" WHERE user_id = $2))",
+            network_id,
+            user_id
+        )
+        .fetch_all(executor)
+        .await
+        .map(|rows| rows.into_iter().map(|row| row.kind).collect())
+    }
+
+    /// Returns every permission grant on `network_id`, for listing/management
+    /// by a network's admin.
+    pub(crate) async fn all_for_network<'e, E: PgExecutor<'e>>(
+        executor: E,
+        network_id: Id,
+    ) -> Result<Vec<Self>, SqlxError> {
+        query_as!(
+            Self,
+            "SELECT id, network_id, user_id, group_id, \
+            kind \"kind: NetworkPermissionKind\" FROM network_permission \
+            WHERE network_id = $1",
+            network_id
+        )
+        .fetch_all(executor)
+        .await
+    }
+
+    /// Returns the ids of every network `user_id` has at least one grant on.
+    pub(crate) async fn network_ids_for_user<'e, E: PgExecutor<'e>>(
+        executor: E,
+        user_id: Id,
+    ) -> Result<Vec<Id>, SqlxError> {
+        query_as!(
+            NetworkIdRow,
+            "SELECT DISTINCT network_id FROM network_permission \
+            WHERE user_id = $1 OR group_id IN \
+            (SELECT group_id FROM \"group_user\" WHERE user_id = $1)",
+            user_id
+        )
+        .fetch_all(executor)
+        .await
+        .map(|rows| rows.into_iter().map(|row| row.network_id).collect())
+    }
+}
+
+struct NetworkPermissionRow {
+    kind: NetworkPermissionKind,
+}
+
+struct NetworkIdRow {
+    network_id: Id,
+}
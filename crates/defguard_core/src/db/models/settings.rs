@@ -0,0 +1,106 @@
+use model_derive::Model;
+use sqlx::{query_as, Error as SqlxError, FromRow, PgExecutor};
+use utoipa::ToSchema;
+
+use crate::db::{Id, NoId};
+
+/// Singleton row holding runtime-configurable instance settings. Unlike
+/// [`crate::ServerConfig`] (read once from CLI/env at startup), this is
+/// read fresh from the database on every access, so an admin's change via
+/// [`crate::handlers::settings`] is observed immediately without a restart
+/// by whatever reads the field back from here - currently
+/// `device_approval_required` (`add_device`), `mfa_policy_enforced`
+/// ([`crate::enterprise::mfa_policy`]) and the `smtp_reachable` diagnostics
+/// check. `smtp_server`/`smtp_port`/`enrollment_url`/`instance_name` are
+/// otherwise only round-tripped by this handler until the mail worker and
+/// enrollment flow are switched over to read them too.
+///
+/// This is a first, partial slice of "runtime-configurable settings": it
+/// covers SMTP/enrollment/instance fields only - there is no LDAP or
+/// proxy/URL configuration here yet - and it's a plain DB row re-read per
+/// request rather than a value swapped behind [`crate::appstate::AppState`].
+/// Both are open follow-ups, not this series' final form.
+#[derive(Clone, Debug, Deserialize, FromRow, Model, Serialize, ToSchema)]
+pub struct Settings<I = NoId> {
+    pub id: I,
+    pub instance_name: String,
+    pub smtp_server: Option<String>,
+    pub smtp_port: Option<i32>,
+    pub enrollment_url: Option<String>,
+    /// See [`crate::enterprise::mfa_policy`].
+    pub mfa_policy_enforced: bool,
+    /// When set, a new user device is parked as a pending [`super::auth_request::AuthRequest`]
+    /// instead of being provisioned immediately. See `add_device`.
+    pub device_approval_required: bool,
+}
+
+/// A partial update to [`Settings`]: `None` leaves a field unchanged.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SettingsPatch {
+    pub instance_name: Option<String>,
+    pub smtp_server: Option<String>,
+    pub smtp_port: Option<i32>,
+    pub enrollment_url: Option<String>,
+    pub mfa_policy_enforced: Option<bool>,
+    pub device_approval_required: Option<bool>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            id: NoId,
+            instance_name: "defguard".into(),
+            smtp_server: None,
+            smtp_port: None,
+            enrollment_url: None,
+            mfa_policy_enforced: false,
+            device_approval_required: false,
+        }
+    }
+}
+
+impl Settings<Id> {
+    /// Returns the single settings row, creating it with defaults on first
+    /// access if it doesn't exist yet.
+    pub(crate) async fn find_current<'e, E>(executor: E) -> Result<Self, SqlxError>
+    where
+        E: PgExecutor<'e> + Copy,
+    {
+        let existing = query_as!(
+            Self,
+            "SELECT id, instance_name, smtp_server, smtp_port, enrollment_url, \
+                mfa_policy_enforced, device_approval_required \
+            FROM settings ORDER BY id LIMIT 1"
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        match existing {
+            Some(settings) => Ok(settings),
+            None => Settings::default().save(executor).await,
+        }
+    }
+
+    /// Applies every `Some` field in `patch`, leaving the rest untouched.
+    /// Does not persist - call [`Self::save`] afterwards.
+    pub(crate) fn apply_patch(&mut self, patch: SettingsPatch) {
+        if let Some(instance_name) = patch.instance_name {
+            self.instance_name = instance_name;
+        }
+        if let Some(smtp_server) = patch.smtp_server {
+            self.smtp_server = Some(smtp_server);
+        }
+        if let Some(smtp_port) = patch.smtp_port {
+            self.smtp_port = Some(smtp_port);
+        }
+        if let Some(enrollment_url) = patch.enrollment_url {
+            self.enrollment_url = Some(enrollment_url);
+        }
+        if let Some(mfa_policy_enforced) = patch.mfa_policy_enforced {
+            self.mfa_policy_enforced = mfa_policy_enforced;
+        }
+        if let Some(device_approval_required) = patch.device_approval_required {
+            self.device_approval_required = device_approval_required;
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use model_derive::Model;
+use rand::distributions::{Alphanumeric, DistString};
+use serde_json::Value as JsonValue;
+use sqlx::{query, query_as, Error as SqlxError, FromRow, PgExecutor};
+use utoipa::ToSchema;
+
+use crate::{
+    db::{Id, NoId},
+    server_config,
+};
+
+const SESSION_ID_LENGTH: usize = 32;
+/// Failed proof attempts allowed against a single step-up session before
+/// it's locked out. Kept low since a legitimate caller only ever submits
+/// one proof per flow.
+const MAX_FAILED_ATTEMPTS: i32 = 3;
+/// How long a session stays locked out after exceeding [`MAX_FAILED_ATTEMPTS`].
+const LOCKOUT: TimeDelta = TimeDelta::minutes(15);
+
+/// A single stage of a UIAA-style step-up re-authentication flow.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthFlow {
+    Password,
+    Totp,
+}
+
+/// A short-lived challenge issued when an already-authenticated session
+/// attempts a destructive device operation. The client must complete every
+/// flow required by the deployment (see [`crate::step_up`]) against this
+/// `session_id` before the original request is let through, so a hijacked
+/// session cookie alone isn't enough to delete peers or swap a pubkey.
+#[derive(Clone, Debug, Deserialize, FromRow, Model, Serialize, ToSchema)]
+pub struct StepUpSession<I = NoId> {
+    pub id: I,
+    pub user_id: Id,
+    #[model(ref)]
+    pub session_id: String,
+    pub completed_flows: JsonValue,
+    pub created_at: NaiveDateTime,
+    /// Consecutive failed proof attempts since the last success. Reset to
+    /// `0` by [`Self::mark_completed`].
+    pub failed_attempts: i32,
+    /// `Some` while this session is locked out after too many failed
+    /// attempts; see [`Self::record_failed_attempt`].
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+impl StepUpSession {
+    #[must_use]
+    pub fn new(user_id: Id) -> Self {
+        Self {
+            id: NoId,
+            user_id,
+            session_id: Alphanumeric.sample_string(&mut rand::thread_rng(), SESSION_ID_LENGTH),
+            completed_flows: JsonValue::Array(Vec::new()),
+            created_at: Utc::now().naive_utc(),
+            failed_attempts: 0,
+            locked_until: None,
+        }
+    }
+}
+
+impl StepUpSession<Id> {
+    pub(crate) async fn find_by_session_id<'e, E: PgExecutor<'e>>(
+        executor: E,
+        session_id: &str,
+    ) -> Result<Option<Self>, SqlxError> {
+        query_as!(
+            Self,
+            "SELECT id, user_id, session_id, completed_flows, created_at, \
+                failed_attempts, locked_until \
+            FROM step_up_session WHERE session_id = $1",
+            session_id
+        )
+        .fetch_optional(executor)
+        .await
+    }
+
+    /// `true` if this session is currently locked out after too many failed
+    /// proof attempts. See [`Self::record_failed_attempt`].
+    pub(crate) fn is_locked_out(&self) -> bool {
+        self.locked_until
+            .is_some_and(|locked_until| Utc::now().naive_utc() < locked_until)
+    }
+
+    /// `true` if `user_id` has any step-up session currently locked out after
+    /// too many failed attempts. Checked before a fresh challenge is minted,
+    /// so discarding a session id and starting over doesn't reset the
+    /// attempt counter - a hijacked cookie only gets one lockout's worth of
+    /// guesses, not one per session it mints.
+    pub(crate) async fn user_locked_out<'e, E: PgExecutor<'e>>(
+        executor: E,
+        user_id: Id,
+    ) -> Result<bool, SqlxError> {
+        let locked_until = query!(
+            "SELECT locked_until FROM step_up_session \
+                WHERE user_id = $1 AND locked_until IS NOT NULL \
+                ORDER BY locked_until DESC LIMIT 1",
+            user_id
+        )
+        .fetch_optional(executor)
+        .await?
+        .and_then(|row| row.locked_until);
+
+        Ok(locked_until.is_some_and(|locked_until| Utc::now().naive_utc() < locked_until))
+    }
+
+    /// Records a failed proof attempt, locking the session out for
+    /// [`LOCKOUT`] once [`MAX_FAILED_ATTEMPTS`] is reached.
+    pub(crate) async fn record_failed_attempt<'e, E: PgExecutor<'e>>(
+        &mut self,
+        executor: E,
+    ) -> Result<(), SqlxError> {
+        self.failed_attempts += 1;
+        if self.failed_attempts >= MAX_FAILED_ATTEMPTS {
+            self.locked_until = Some(Utc::now().naive_utc() + LOCKOUT);
+        }
+
+        query!(
+            "UPDATE step_up_session SET failed_attempts = $2, locked_until = $3 WHERE id = $1",
+            self.id,
+            self.failed_attempts,
+            self.locked_until,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// A step-up session only exists to bridge a single re-auth round trip;
+    /// anything older than this is abandoned and must be restarted.
+    pub(crate) fn is_expired(&self) -> bool {
+        let ttl = TimeDelta::seconds(server_config().step_up_auth_ttl_secs);
+        Utc::now().naive_utc() > self.created_at + ttl
+    }
+
+    fn completed(&self) -> Vec<AuthFlow> {
+        serde_json::from_value(self.completed_flows.clone()).unwrap_or_default()
+    }
+
+    /// `true` if this session is still fresh and has completed every flow in
+    /// `required`.
+    pub(crate) fn satisfies(&self, required: &[AuthFlow]) -> bool {
+        !self.is_expired() && required.iter().all(|flow| self.completed().contains(flow))
+    }
+
+    pub(crate) async fn mark_completed<'e, E: PgExecutor<'e>>(
+        &mut self,
+        executor: E,
+        flow: AuthFlow,
+    ) -> Result<(), SqlxError> {
+        let mut completed = self.completed();
+        if !completed.contains(&flow) {
+            completed.push(flow);
+        }
+        self.completed_flows =
+            serde_json::to_value(&completed).expect("flow list is always serializable");
+        self.failed_attempts = 0;
+
+        query!(
+            "UPDATE step_up_session SET completed_flows = $2, failed_attempts = 0 WHERE id = $1",
+            self.id,
+            self.completed_flows,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use super::*;
+    use crate::db::{setup_pool, User};
+
+    async fn fixture_user(pool: &sqlx::PgPool) -> Id {
+        User::new(
+            "step-up-user",
+            Some("hunter2"),
+            "Test",
+            "Test",
+            "step-up-user@test.com",
+            None,
+        )
+        .save(pool)
+        .await
+        .unwrap()
+        .id
+    }
+
+    #[sqlx::test]
+    async fn test_user_locked_out_follows_failed_attempts_across_sessions(
+        _: PgPoolOptions,
+        options: PgConnectOptions,
+    ) {
+        let pool = setup_pool(options).await;
+        let user_id = fixture_user(&pool).await;
+
+        assert!(!StepUpSession::user_locked_out(&pool, user_id).await.unwrap());
+
+        // Discarding the session id after MAX_FAILED_ATTEMPTS and minting a
+        // new one must not reset the lockout - the new session still sees
+        // the user as locked out.
+        let mut first = StepUpSession::new(user_id).save(&pool).await.unwrap();
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            first.record_failed_attempt(&pool).await.unwrap();
+        }
+        assert!(first.is_locked_out());
+        assert!(StepUpSession::user_locked_out(&pool, user_id).await.unwrap());
+
+        let _second = StepUpSession::new(user_id).save(&pool).await.unwrap();
+        assert!(StepUpSession::user_locked_out(&pool, user_id).await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn test_user_locked_out_ignores_other_users(
+        _: PgPoolOptions,
+        options: PgConnectOptions,
+    ) {
+        let pool = setup_pool(options).await;
+        let locked_user = fixture_user(&pool).await;
+
+        let mut session = StepUpSession::new(locked_user).save(&pool).await.unwrap();
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            session.record_failed_attempt(&pool).await.unwrap();
+        }
+
+        let other_user = User::new(
+            "step-up-other",
+            Some("hunter2"),
+            "Test",
+            "Test",
+            "step-up-other@test.com",
+            None,
+        )
+        .save(&pool)
+        .await
+        .unwrap()
+        .id;
+        assert!(!StepUpSession::user_locked_out(&pool, other_user)
+            .await
+            .unwrap());
+    }
+}
@@ -0,0 +1,172 @@
+//! Group- and organization-level enforcement of a second authentication
+//! factor. Complements per-user MFA settings (see [`crate::db::User`]) with a
+//! policy that can be turned on globally or for individual groups.
+//!
+//! Neither [`enforce_login_mfa_policy`] nor [`recheck_after_factor_removal`]
+//! is called from the login handler in `crate::handlers::auth` or from the
+//! factor-removal handlers, so a policy-covered user without a second factor
+//! can still complete login, and losing a last factor does not force
+//! re-enrollment. The tests below call both functions directly against
+//! fixtures.
+
+use sqlx::PgPool;
+
+use crate::{
+    db::{models::settings::Settings, Group, Id, User},
+    error::WebError,
+};
+
+/// Returns `true` if `user` currently has at least one active second factor:
+/// TOTP, email MFA, or a registered WebAuthn key.
+async fn has_active_second_factor(pool: &PgPool, user: &User<Id>) -> Result<bool, WebError> {
+    if user.totp_enabled || user.email_mfa_enabled {
+        return Ok(true);
+    }
+    let webauthn_keys = crate::db::models::webauthn::WebAuthn::find_by_user_id(pool, user.id)
+        .await
+        .map_err(WebError::from)?;
+    Ok(!webauthn_keys.is_empty())
+}
+
+/// Returns `true` if `user` belongs to a group for which the "require second
+/// factor" flag is set, or if the global MFA policy toggle is enabled in
+/// [`Settings`].
+async fn mfa_policy_applies(pool: &PgPool, user: &User<Id>) -> Result<bool, WebError> {
+    let settings = Settings::find_current(pool).await?;
+    if settings.mfa_policy_enforced {
+        return Ok(true);
+    }
+
+    let groups = Group::find_by_member(pool, user.id)
+        .await
+        .map_err(WebError::from)?;
+    Ok(groups.iter().any(|group| group.require_mfa))
+}
+
+/// Checked right before a login is completed. If the organization's MFA
+/// policy applies to `user` and they have no active second factor, login is
+/// rejected so the client can be redirected into MFA enrollment instead of
+/// receiving a fully authenticated session.
+pub async fn enforce_login_mfa_policy(pool: &PgPool, user: &User<Id>) -> Result<(), WebError> {
+    if mfa_policy_applies(pool, user).await? && !has_active_second_factor(pool, user).await? {
+        return Err(WebError::MfaPolicyViolation(format!(
+            "User {} is covered by an MFA policy but has no active second factor",
+            user.username
+        )));
+    }
+    Ok(())
+}
+
+/// Called whenever a user's last second factor is removed - either by an
+/// admin revoking it or by the user deleting their own recovery codes or
+/// WebAuthn key. If the MFA policy still applies to this user, their account
+/// is flagged so the *next* login is forced back into MFA enrollment rather
+/// than silently leaving a policy-covered account without a second factor.
+pub async fn recheck_after_factor_removal(
+    pool: &PgPool,
+    user: &mut User<Id>,
+) -> Result<(), WebError> {
+    if mfa_policy_applies(pool, user).await? && !has_active_second_factor(pool, user).await? {
+        warn!(
+            "User {} lost their last second factor while still covered by an MFA policy; \
+            flagging account for mandatory re-enrollment",
+            user.username
+        );
+        user.mfa_enrollment_required = true;
+        user.save(pool).await.map_err(WebError::from)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use super::*;
+    use crate::db::setup_pool;
+
+    async fn user_without_factor(pool: &PgPool, username: &str) -> User<Id> {
+        User::new(
+            username,
+            Some("hunter2"),
+            "Test",
+            "Test",
+            &format!("{username}@test.com"),
+            None,
+        )
+        .save(pool)
+        .await
+        .unwrap()
+    }
+
+    async fn enforce_global_mfa_policy(pool: &PgPool) {
+        let mut settings = Settings::find_current(pool).await.unwrap();
+        settings.mfa_policy_enforced = true;
+        settings.save(pool).await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_enforce_login_mfa_policy_allows_when_policy_not_enforced(
+        _: PgPoolOptions,
+        options: PgConnectOptions,
+    ) {
+        let pool = setup_pool(options).await;
+        let user = user_without_factor(&pool, "no-policy-user").await;
+        assert!(enforce_login_mfa_policy(&pool, &user).await.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_enforce_login_mfa_policy_blocks_user_without_factor(
+        _: PgPoolOptions,
+        options: PgConnectOptions,
+    ) {
+        let pool = setup_pool(options).await;
+        enforce_global_mfa_policy(&pool).await;
+
+        let user = user_without_factor(&pool, "policy-user").await;
+        let result = enforce_login_mfa_policy(&pool, &user).await;
+        assert!(matches!(result, Err(WebError::MfaPolicyViolation(_))));
+    }
+
+    #[sqlx::test]
+    async fn test_enforce_login_mfa_policy_allows_user_with_factor(
+        _: PgPoolOptions,
+        options: PgConnectOptions,
+    ) {
+        let pool = setup_pool(options).await;
+        enforce_global_mfa_policy(&pool).await;
+
+        let mut user = user_without_factor(&pool, "totp-user").await;
+        user.totp_enabled = true;
+        let user = user.save(&pool).await.unwrap();
+
+        assert!(enforce_login_mfa_policy(&pool, &user).await.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_recheck_after_factor_removal_flags_account_when_policy_applies(
+        _: PgPoolOptions,
+        options: PgConnectOptions,
+    ) {
+        let pool = setup_pool(options).await;
+        enforce_global_mfa_policy(&pool).await;
+
+        let mut user = user_without_factor(&pool, "removed-factor-user").await;
+        assert!(!user.mfa_enrollment_required);
+
+        recheck_after_factor_removal(&pool, &mut user).await.unwrap();
+        assert!(user.mfa_enrollment_required);
+    }
+
+    #[sqlx::test]
+    async fn test_recheck_after_factor_removal_noop_when_policy_does_not_apply(
+        _: PgPoolOptions,
+        options: PgConnectOptions,
+    ) {
+        let pool = setup_pool(options).await;
+        let mut user = user_without_factor(&pool, "unaffected-user").await;
+
+        recheck_after_factor_removal(&pool, &mut user).await.unwrap();
+        assert!(!user.mfa_enrollment_required);
+    }
+}
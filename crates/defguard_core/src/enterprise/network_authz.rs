@@ -0,0 +1,123 @@
+//! Per-network delegated administration.
+//!
+//! Historically every network-management handler gated on the single
+//! global [`crate::auth::AdminRole`]. These extractors let a user (or group)
+//! be scoped to managing just one network instead, via a `network_permission`
+//! grant, while global admins keep full access to everything as before.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{FromRef, FromRequestParts, Path},
+    http::request::Parts,
+};
+use sqlx::PgExecutor;
+
+use crate::{
+    appstate::AppState,
+    auth::SessionInfo,
+    db::{models::network_permission::{NetworkPermission, NetworkPermissionKind}, Id},
+    error::WebError,
+};
+
+async fn has_permission<S>(
+    parts: &mut Parts,
+    state: &S,
+    kind: NetworkPermissionKind,
+) -> Result<(), WebError>
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    let session = SessionInfo::from_request_parts(parts, state).await?;
+    if session.is_admin {
+        return Ok(());
+    }
+
+    // `HashMap` rather than a scalar/tuple, since some routes this guards
+    // (e.g. `/network/{network_id}/permission/{permission_id}`) carry extra
+    // path params alongside `network_id`.
+    let Path(params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+        .await
+        .map_err(|_| WebError::ObjectNotFound("network_id not present in path".into()))?;
+    let network_id = params
+        .get("network_id")
+        .and_then(|id| id.parse::<Id>().ok())
+        .ok_or_else(|| WebError::ObjectNotFound("network_id not present in path".into()))?;
+
+    let appstate = AppState::from_ref(state);
+    let grants =
+        NetworkPermission::for_user_and_network(&appstate.pool, session.user.id, network_id)
+            .await?;
+
+    if grants.contains(&kind) {
+        Ok(())
+    } else {
+        Err(WebError::Forbidden(format!(
+            "User {} lacks {kind:?} permission on network {network_id}",
+            session.user.username
+        )))
+    }
+}
+
+macro_rules! network_role_extractor {
+    ($name:ident, $kind:expr) => {
+        pub struct $name;
+
+        impl<S> FromRequestParts<S> for $name
+        where
+            S: Send + Sync,
+            AppState: FromRef<S>,
+        {
+            type Rejection = WebError;
+
+            async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+                has_permission(parts, state, $kind).await?;
+                Ok(Self)
+            }
+        }
+    };
+}
+
+network_role_extractor!(ManageNetworkRole, NetworkPermissionKind::ManageNetwork);
+network_role_extractor!(ManageNetworkDevicesRole, NetworkPermissionKind::ManageDevices);
+network_role_extractor!(ViewNetworkStatsRole, NetworkPermissionKind::ViewStats);
+
+/// Checks that `user_id` may manage devices on every network in
+/// `network_ids`, either as a global admin, the device's own owner, or via a
+/// `ManageDevices` grant on each network individually. Used by device
+/// handlers that aren't themselves scoped to a single `network_id` path
+/// param (so the [`ManageNetworkDevicesRole`] extractor doesn't apply), but
+/// whose blanket `CanManageDevices` gate should still be narrowed for a
+/// delegated, per-network admin.
+///
+/// `is_owner` mirrors the admin-or-self semantics of
+/// [`device_for_admin_or_self`](crate::handlers::device_for_admin_or_self):
+/// a user managing their own device never needs a `ManageDevices` grant,
+/// since the delegation this check exists for is about letting someone
+/// manage *other* users' devices on a network they don't globally administer.
+pub(crate) async fn ensure_can_manage_device_networks<'e, E>(
+    executor: E,
+    user_id: Id,
+    is_admin: bool,
+    is_owner: bool,
+    network_ids: &[Id],
+) -> Result<(), WebError>
+where
+    E: PgExecutor<'e> + Copy,
+{
+    if is_admin || is_owner {
+        return Ok(());
+    }
+
+    for network_id in network_ids {
+        let grants = NetworkPermission::for_user_and_network(executor, user_id, *network_id).await?;
+        if !grants.contains(&NetworkPermissionKind::ManageDevices) {
+            return Err(WebError::Forbidden(format!(
+                "User lacks ManageDevices permission on network {network_id}"
+            )));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use std::{path::PathBuf, process::Stdio};
+
+use axum::{extract::State, http::StatusCode};
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use tokio::{io::AsyncReadExt, process::Command};
+use utoipa::ToSchema;
+
+use super::{ApiResponse, ApiResult};
+use crate::{
+    appstate::AppState,
+    auth::SessionInfo,
+    enterprise::activity_log_stream::ActivityLogStream,
+    error::WebError,
+    events::{ActivityLogEvent, ActivityLogEventType},
+    server_config,
+};
+
+/// Metadata about the most recently completed database backup.
+#[derive(Clone, Serialize, ToSchema)]
+pub(crate) struct BackupInfo {
+    pub(crate) created_at: NaiveDateTime,
+    pub(crate) size_bytes: u64,
+    pub(crate) file_name: String,
+}
+
+fn backup_directory() -> PathBuf {
+    server_config().backup_dir.clone()
+}
+
+/// Runs `pg_dump` against the configured database, encrypts the result with
+/// the server's configured backup passphrase (AES-256-CBC via `openssl enc`)
+/// and writes it into the backup directory. Returns metadata describing the
+/// produced file.
+async fn create_backup_file(database_url: &str) -> Result<BackupInfo, WebError> {
+    let backup_dir = backup_directory();
+    tokio::fs::create_dir_all(&backup_dir).await.map_err(|err| {
+        error!("Failed to create backup directory: {err}");
+        WebError::Http(StatusCode::INTERNAL_SERVER_ERROR)
+    })?;
+
+    let created_at = Utc::now().naive_utc();
+    let file_name = format!(
+        "defguard-backup-{}.sql.enc",
+        created_at.format("%Y%m%d%H%M%S")
+    );
+    let file_path = backup_dir.join(&file_name);
+    let passphrase = &server_config().backup_encryption_secret;
+
+    // `pg_dump` falls back to `PGDATABASE` when no `--dbname` is given on the
+    // command line, and libpq treats a `postgres://` value there as a full
+    // conninfo string (not just a database name) - so the connection string,
+    // credentials included, never appears in argv/`ps`/`/proc/<pid>/cmdline`,
+    // same as the passphrase below.
+    let mut pg_dump = Command::new("pg_dump")
+        .env("PGDATABASE", database_url)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            error!("Failed to spawn pg_dump: {err}");
+            WebError::Http(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+
+    let pg_dump_stdout = pg_dump
+        .stdout
+        .take()
+        .ok_or_else(|| {
+            error!("Failed to capture pg_dump output");
+            WebError::Http(StatusCode::INTERNAL_SERVER_ERROR)
+        })?
+        .into_std()
+        .await;
+
+    // Drain pg_dump's stderr concurrently with openssl reading its stdout -
+    // otherwise any NOTICE/WARNING output fills the pipe buffer and blocks
+    // pg_dump forever, since nothing else would be reading from it until
+    // openssl is done.
+    let mut pg_dump_stderr = pg_dump.stderr.take().ok_or_else(|| {
+        error!("Failed to capture pg_dump stderr");
+        WebError::Http(StatusCode::INTERNAL_SERVER_ERROR)
+    })?;
+    let pg_dump_stderr = tokio::spawn(async move {
+        let mut stderr = String::new();
+        let _ = pg_dump_stderr.read_to_string(&mut stderr).await;
+        stderr
+    });
+
+    // Passed via the child's environment rather than argv so it doesn't show
+    // up in `ps`/`/proc/<pid>/cmdline` for other local users.
+    let output = Command::new("openssl")
+        .args(["enc", "-aes-256-cbc", "-pbkdf2", "-pass", "env:DEFGUARD_BACKUP_PASSPHRASE"])
+        .env("DEFGUARD_BACKUP_PASSPHRASE", passphrase)
+        .arg("-out")
+        .arg(&file_path)
+        .stdin(Stdio::from(pg_dump_stdout))
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .map_err(|err| {
+            error!("Failed to spawn openssl: {err}");
+            WebError::Http(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+
+    // Reap pg_dump now that openssl has hit EOF on its stdin, instead of
+    // leaking a zombie process on every backup.
+    let pg_dump_status = pg_dump.wait().await.map_err(|err| {
+        error!("Failed to wait for pg_dump: {err}");
+        WebError::Http(StatusCode::INTERNAL_SERVER_ERROR)
+    })?;
+    let pg_dump_stderr = pg_dump_stderr.await.unwrap_or_default();
+    if !pg_dump_status.success() {
+        error!("pg_dump failed with {pg_dump_status}: {pg_dump_stderr}");
+        return Err(WebError::Http(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    if !output.status.success() {
+        let mut stderr = String::new();
+        let _ = output.stderr.as_slice().read_to_string(&mut stderr).await;
+        error!("Encryption of backup failed with {}: {stderr}", output.status);
+        return Err(WebError::Http(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    let size_bytes = tokio::fs::metadata(&file_path)
+        .await
+        .map_err(|err| {
+            error!("Failed to stat backup file: {err}");
+            WebError::Http(StatusCode::INTERNAL_SERVER_ERROR)
+        })?
+        .len();
+
+    Ok(BackupInfo {
+        created_at,
+        size_bytes,
+        file_name,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/backup",
+    responses(
+        (status = 201, description = "Successfully created a database backup.", body = BackupInfo),
+        (status = 401, description = "Unauthorized to trigger a backup.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to trigger a backup.", body = ApiResponse, example = json!({"msg": "requires privileged access"})),
+        (status = 500, description = "Backup failed.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn trigger_backup(
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    if !session.is_admin {
+        return Err(WebError::Forbidden("requires privileged access".into()));
+    }
+
+    info!(
+        "User {} triggered an on-demand database backup",
+        session.user.username
+    );
+    let backup = create_backup_file(&server_config().database_url).await?;
+
+    ActivityLogStream::log(
+        &appstate.pool,
+        ActivityLogEvent::new(
+            session.user.id,
+            session.context.ip,
+            ActivityLogEventType::DatabaseBackupCreated {
+                file_name: backup.file_name.clone(),
+                size_bytes: backup.size_bytes,
+            },
+        ),
+    )
+    .await?;
+
+    info!(
+        "Database backup {} ({} bytes) created by {}",
+        backup.file_name, backup.size_bytes, session.user.username
+    );
+
+    Ok(ApiResponse::new(
+        serde_json::json!(backup),
+        axum::http::StatusCode::CREATED,
+    ))
+}
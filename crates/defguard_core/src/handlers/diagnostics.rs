@@ -0,0 +1,209 @@
+use std::{
+    net::{ToSocketAddrs, UdpSocket},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::extract::State;
+use serde::Serialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use super::{backup::BackupInfo, ApiResponse, ApiResult};
+use crate::{
+    appstate::AppState, auth::SessionInfo, db::models::settings::Settings,
+    enterprise::license::LicenseError, VERSION,
+};
+
+/// A best-effort SNTP server used to estimate local clock drift.
+/// We only ever read the transmit timestamp, never change local time.
+const NTP_SERVER: &str = "pool.ntp.org:123";
+const NTP_TIMEOUT: Duration = Duration::from_secs(2);
+/// Seconds between 1900-01-01 (NTP epoch) and 1970-01-01 (Unix epoch).
+const NTP_UNIX_EPOCH_DELTA: u64 = 2_208_988_800;
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct DiagnosticInfo {
+    pub(crate) version: &'static str,
+    pub(crate) postgres_version: Option<String>,
+    pub(crate) containerized: bool,
+    /// Difference between local system time and an NTP server, in seconds.
+    /// `None` if the NTP server could not be reached.
+    pub(crate) ntp_drift_seconds: Option<f64>,
+    pub(crate) smtp_reachable: bool,
+    pub(crate) license_status: String,
+    pub(crate) last_backup: Option<BackupInfo>,
+}
+
+/// Finds the most recently created backup file in the configured backup
+/// directory, if any. Returns `None` on any I/O error, since an unreadable
+/// backup directory shouldn't prevent the rest of the diagnostics from being
+/// returned.
+fn last_backup_info() -> Option<BackupInfo> {
+    let backup_dir = crate::server_config().backup_dir.clone();
+    let mut newest: Option<(std::time::SystemTime, BackupInfo)> = None;
+
+    for entry in std::fs::read_dir(backup_dir).ok()?.flatten() {
+        let metadata = entry.metadata().ok()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata.modified().ok()?;
+        if newest.as_ref().is_none_or(|(ts, _)| modified > *ts) {
+            let created_at = chrono::DateTime::<chrono::Utc>::from(modified).naive_utc();
+            newest = Some((
+                modified,
+                BackupInfo {
+                    created_at,
+                    size_bytes: metadata.len(),
+                    file_name: entry.file_name().to_string_lossy().into_owned(),
+                },
+            ));
+        }
+    }
+
+    newest.map(|(_, info)| info)
+}
+
+/// Returns `true` if defguard appears to be running inside a container.
+fn is_containerized() -> bool {
+    std::path::Path::new("/.dockerenv").exists()
+        || std::fs::read_to_string("/proc/1/cgroup")
+            .map(|contents| contents.contains("docker") || contents.contains("kubepods"))
+            .unwrap_or(false)
+}
+
+/// Performs a minimal SNTP query and returns the clock drift, in seconds,
+/// between this host and the queried server. Never fails loudly - any
+/// network error simply yields `None`, since this is a diagnostic aid.
+fn ntp_drift_seconds() -> Option<f64> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.set_read_timeout(Some(NTP_TIMEOUT)).ok()?;
+    socket.set_write_timeout(Some(NTP_TIMEOUT)).ok()?;
+    socket.connect(NTP_SERVER).ok()?;
+
+    let mut packet = [0u8; 48];
+    // LI = 0, VN = 3, Mode = 3 (client)
+    packet[0] = 0b0001_1011;
+    socket.send(&packet).ok()?;
+
+    let request_sent = SystemTime::now();
+    let mut response = [0u8; 48];
+    socket.recv(&mut response).ok()?;
+    let roundtrip = SystemTime::now().duration_since(request_sent).ok()?;
+
+    // The transmit timestamp occupies bytes 40..48 (seconds in 40..44).
+    let ntp_seconds = u32::from_be_bytes(response[40..44].try_into().ok()?);
+    let server_unix_seconds = u64::from(ntp_seconds).checked_sub(NTP_UNIX_EPOCH_DELTA)?;
+
+    let local_unix_seconds = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    // Account for roughly half the round-trip while the response was in flight.
+    let adjusted_server_seconds = server_unix_seconds as f64 + roundtrip.as_secs_f64() / 2.0;
+
+    Some(local_unix_seconds as f64 - adjusted_server_seconds)
+}
+
+/// Reads the live SMTP host/port from [`Settings`]. Returns `None` when
+/// either is unset or the settings can't be loaded; [`smtp_reachable`] then
+/// falls back to the static startup configuration.
+async fn resolve_smtp_target(pool: &PgPool) -> Option<(String, u16)> {
+    let settings = Settings::find_current(pool).await.ok()?;
+    match (settings.smtp_server, settings.smtp_port) {
+        (Some(server), Some(port)) => Some((server, port as u16)),
+        _ => None,
+    }
+}
+
+/// Attempts a TCP connection to the configured SMTP server to confirm it is
+/// reachable. This does not send any mail. `live_target` is the host/port
+/// pair resolved from [`Settings`] (if an admin has set one), which takes
+/// priority over the static startup configuration.
+fn smtp_reachable(live_target: Option<(String, u16)>) -> bool {
+    use std::net::TcpStream;
+
+    let address = match live_target {
+        Some((host, port)) => (host.as_str(), port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next()),
+        None => server_config().smtp_server_address().ok(),
+    };
+    let Some(address) = address else {
+        return false;
+    };
+    TcpStream::connect_timeout(&address, NTP_TIMEOUT).is_ok()
+}
+
+fn license_status() -> String {
+    match crate::enterprise::license::get_cached_license() {
+        Some(Ok(())) => "valid".into(),
+        Some(Err(LicenseError::LicenseNotFound)) | None => "not_configured".into(),
+        Some(Err(LicenseError::SignatureMismatch | LicenseError::InvalidSignature)) => {
+            "tampered".into()
+        }
+        Some(Err(_)) => "invalid".into(),
+    }
+}
+
+async fn postgres_version(pool: &PgPool) -> Option<String> {
+    sqlx::query_scalar!("SELECT version()")
+        .fetch_one(pool)
+        .await
+        .ok()
+        .flatten()
+}
+
+fn server_config() -> &'static crate::ServerConfig {
+    crate::server_config()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/diagnostics",
+    responses(
+        (status = 200, description = "Successfully retrieved runtime diagnostics.", body = DiagnosticInfo),
+        (status = 401, description = "Unauthorized to view diagnostics.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to view diagnostics.", body = ApiResponse, example = json!({"msg": "requires privileged access"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn diagnostics(
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    debug!(
+        "User {} fetching runtime diagnostics",
+        session.user.username
+    );
+    if !session.is_admin {
+        return Err(crate::error::WebError::Forbidden(
+            "requires privileged access".into(),
+        ));
+    }
+
+    let live_smtp_target = resolve_smtp_target(&appstate.pool).await;
+
+    // `ntp_drift_seconds` and `smtp_reachable` perform blocking socket I/O, so
+    // they're run on the blocking thread pool rather than stalling this
+    // handler's async worker thread.
+    let (ntp_drift_seconds, smtp_reachable) = tokio::task::spawn_blocking(move || {
+        (ntp_drift_seconds(), smtp_reachable(live_smtp_target))
+    })
+    .await
+    .unwrap_or((None, false));
+
+    let info = DiagnosticInfo {
+        version: VERSION,
+        postgres_version: postgres_version(&appstate.pool).await,
+        containerized: is_containerized(),
+        ntp_drift_seconds,
+        smtp_reachable,
+        license_status: license_status(),
+        last_backup: last_backup_info(),
+    };
+
+    info!("User {} fetched runtime diagnostics", session.user.username);
+    Ok(ApiResponse::new(serde_json::json!(info), axum::http::StatusCode::OK))
+}
@@ -0,0 +1,134 @@
+use thiserror::Error;
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
+use utoipa::ToSchema;
+
+use super::{ApiResponse, ApiResult};
+use crate::{appstate::AppState, auth::SessionInfo, error::WebError, templates::TemplateLocation};
+
+/// A mail request handed off to the mail worker task.
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub content: String,
+    /// Lets the caller wait for (and react to) the actual delivery result,
+    /// e.g. to distinguish a connection failure from an auth failure.
+    pub result_tx: Option<oneshot::Sender<Result<(), MailError>>>,
+}
+
+/// Failure reasons for a mail delivery attempt, kept distinct so callers
+/// (like the SMTP test endpoint) can surface an actionable message instead
+/// of a generic "Internal server error".
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("Could not connect to SMTP server: {0}")]
+    ConnectionError(String),
+    #[error("TLS negotiation with SMTP server failed: {0}")]
+    TlsError(String),
+    #[error("SMTP authentication failed: {0}")]
+    AuthError(String),
+    #[error("Failed to send mail: {0}")]
+    Other(String),
+}
+
+pub(crate) fn send_new_device_added_email(
+    device_name: &str,
+    device_pubkey: &str,
+    locations: &[TemplateLocation],
+    to: &str,
+    mail_tx: &UnboundedSender<Mail>,
+    ip: Option<&str>,
+    device_info: Option<&str>,
+) -> Result<(), WebError> {
+    let content = crate::templates::new_device_added_mail(
+        device_name,
+        device_pubkey,
+        locations,
+        ip,
+        device_info,
+    )
+    .map_err(WebError::TemplateError)?;
+
+    mail_tx
+        .send(Mail {
+            to: to.to_string(),
+            subject: "New device added to your account".to_string(),
+            content,
+            result_tx: None,
+        })
+        .map_err(|err| WebError::Serialization(err.to_string()))?;
+
+    Ok(())
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct TestMailData {
+    pub to: String,
+}
+
+/// Sends a single test email through the currently configured SMTP settings
+/// and waits for the mail worker's delivery result, so the caller gets a
+/// definitive success/failure answer rather than a fire-and-forget status.
+#[utoipa::path(
+    post,
+    path = "/api/v1/mail/test",
+    request_body = TestMailData,
+    responses(
+        (status = 200, description = "Test email was sent successfully.", body = ApiResponse),
+        (status = 400, description = "Test email could not be sent.", body = ApiResponse, example = json!({"msg": "SMTP authentication failed: invalid credentials"})),
+        (status = 401, description = "Unauthorized to test SMTP configuration.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to test SMTP configuration.", body = ApiResponse, example = json!({"msg": "requires privileged access"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn test_mail(
+    session: SessionInfo,
+    axum::extract::State(appstate): axum::extract::State<AppState>,
+    axum::extract::Json(data): axum::extract::Json<TestMailData>,
+) -> ApiResult {
+    if !session.is_admin {
+        return Err(WebError::Forbidden("requires privileged access".into()));
+    }
+
+    debug!(
+        "User {} testing SMTP configuration against {}",
+        session.user.username, data.to
+    );
+
+    let (result_tx, result_rx) = oneshot::channel();
+    appstate
+        .mail_tx
+        .send(Mail {
+            to: data.to.clone(),
+            subject: "defguard SMTP test".to_string(),
+            content: "This is a test email sent from defguard to verify your SMTP configuration."
+                .to_string(),
+            result_tx: Some(result_tx),
+        })
+        .map_err(|err| WebError::Serialization(err.to_string()))?;
+
+    match result_rx.await {
+        Ok(Ok(())) => {
+            info!(
+                "User {} successfully tested SMTP configuration",
+                session.user.username
+            );
+            Ok(ApiResponse::new(
+                serde_json::json!({ "msg": "Test email sent successfully" }),
+                axum::http::StatusCode::OK,
+            ))
+        }
+        Ok(Err(mail_error)) => {
+            warn!(
+                "SMTP test triggered by {} failed: {mail_error}",
+                session.user.username
+            );
+            Err(WebError::MailError(mail_error))
+        }
+        Err(_) => Err(WebError::MailError(MailError::Other(
+            "Mail worker did not report a result".into(),
+        ))),
+    }
+}
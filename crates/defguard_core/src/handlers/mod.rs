@@ -4,7 +4,6 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use axum_client_ip::InsecureClientIp;
 use axum_extra::{headers::UserAgent, TypedHeader};
 use serde_json::{json, Value};
 use sqlx::PgPool;
@@ -16,6 +15,7 @@ use crate::db::Device;
 use crate::{
     appstate::AppState,
     auth::SessionInfo,
+    client_ip::resolve_client_ip,
     db::{Id, NoId, User, UserInfo, WebHook},
     enterprise::{db::models::acl::AclError, license::LicenseError},
     error::WebError,
@@ -26,6 +26,8 @@ use crate::{
 pub(crate) mod activity_log;
 pub(crate) mod app_info;
 pub(crate) mod auth;
+pub(crate) mod backup;
+pub(crate) mod diagnostics;
 pub(crate) mod forward_auth;
 pub(crate) mod group;
 pub(crate) mod mail;
@@ -45,6 +47,7 @@ pub(crate) mod webhooks;
 pub mod wireguard;
 #[cfg(feature = "worker")]
 pub mod worker;
+pub(crate) mod ws;
 pub(crate) mod yubikey;
 
 pub(crate) static SESSION_COOKIE_NAME: &str = "defguard_session";
@@ -82,6 +85,10 @@ impl From<WebError> for ApiResponse {
                 error!(msg);
                 ApiResponse::new(json!({ "msg": msg }), StatusCode::FORBIDDEN)
             }
+            WebError::MfaPolicyViolation(msg) => {
+                warn!(msg);
+                ApiResponse::new(json!({ "msg": msg }), StatusCode::FORBIDDEN)
+            }
             WebError::DbError(_)
             | WebError::Grpc(_)
             | WebError::Ldap(_)
@@ -174,6 +181,10 @@ impl From<WebError> for ApiResponse {
                     StatusCode::INTERNAL_SERVER_ERROR,
                 )
             }
+            WebError::MailError(err) => {
+                warn!("{err}");
+                ApiResponse::new(json!({ "msg": err.to_string() }), StatusCode::BAD_REQUEST)
+            }
             WebError::LicenseError(err) => match err {
                 LicenseError::DecodeError(msg) | LicenseError::InvalidLicense(msg) => {
                     warn!(msg);
@@ -276,6 +287,11 @@ pub struct GroupInfo {
     pub members: Vec<String>,
     pub vpn_locations: Vec<String>,
     pub is_admin: bool,
+    /// Whether members of this group must have an active second factor
+    /// configured. Read by [`crate::enterprise::mfa_policy`] together with
+    /// the global MFA policy toggle in settings; the login flow does not
+    /// call that check, so the flag is not enforced at login.
+    pub require_mfa: bool,
 }
 
 impl GroupInfo {
@@ -286,6 +302,7 @@ impl GroupInfo {
         members: Vec<String>,
         vpn_locations: Vec<String>,
         is_admin: bool,
+        require_mfa: bool,
     ) -> Self {
         Self {
             id,
@@ -293,6 +310,7 @@ impl GroupInfo {
             members,
             vpn_locations,
             is_admin,
+            require_mfa,
         }
     }
 }
@@ -303,15 +321,22 @@ pub struct EditGroupInfo {
     pub name: String,
     pub members: Vec<String>,
     pub is_admin: bool,
+    pub require_mfa: bool,
 }
 
 impl EditGroupInfo {
     #[must_use]
-    pub fn new<S: Into<String>>(name: S, members: Vec<String>, is_admin: bool) -> Self {
+    pub fn new<S: Into<String>>(
+        name: S,
+        members: Vec<String>,
+        is_admin: bool,
+        require_mfa: bool,
+    ) -> Self {
         Self {
             name: name.into(),
             members,
             is_admin,
+            require_mfa,
         }
     }
 }
@@ -471,9 +496,7 @@ where
         let TypedHeader(user_agent) = TypedHeader::<UserAgent>::from_request_parts(parts, state)
             .await
             .map_err(|_| WebError::BadRequest("Missing UserAgent header".to_string()))?;
-        let InsecureClientIp(insecure_ip) = InsecureClientIp::from_request_parts(parts, state)
-            .await
-            .map_err(|_| WebError::BadRequest("Missing client IP".to_string()))?;
+        let client_ip = resolve_client_ip(parts, state).await?;
         let session = if let Some(cached) = parts.extensions.get::<SessionInfo>() {
             cached.clone()
         } else {
@@ -485,7 +508,7 @@ where
         Ok(ApiRequestContext::new(
             session.user.id,
             session.user.username,
-            insecure_ip,
+            client_ip,
             user_agent.to_string(),
         ))
     }
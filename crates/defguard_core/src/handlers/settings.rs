@@ -0,0 +1,102 @@
+use axum::{extract::State, http::StatusCode};
+
+use super::{ApiResponse, ApiResult};
+use crate::{
+    appstate::AppState,
+    auth::SessionInfo,
+    db::models::settings::{Settings, SettingsPatch},
+    error::WebError,
+};
+
+/// Validates a [`SettingsPatch`] before it's allowed to be persisted.
+/// Keeps the checks close to the fields they guard rather than in a
+/// generic "is this valid" catch-all, mirroring how individual requests
+/// already validate their own payloads (e.g. [`super::wireguard::parse_address_list`]).
+///
+/// Rejections use the generic [`WebError::Http`] `UNPROCESSABLE_ENTITY` arm
+/// rather than [`WebError::BadRequest`] - the specific reason is logged
+/// here, the same tradeoff [`super::backup`] makes for its own failures.
+fn validate_settings_patch(patch: &SettingsPatch) -> Result<(), WebError> {
+    if let Some(instance_name) = &patch.instance_name {
+        if instance_name.trim().is_empty() {
+            warn!("Rejected settings update: instance name cannot be empty");
+            return Err(WebError::Http(StatusCode::UNPROCESSABLE_ENTITY));
+        }
+    }
+    if let Some(smtp_port) = patch.smtp_port {
+        if !(1..=65535).contains(&smtp_port) {
+            warn!("Rejected settings update: SMTP port {smtp_port} is not a valid TCP port");
+            return Err(WebError::Http(StatusCode::UNPROCESSABLE_ENTITY));
+        }
+    }
+    if let Some(url) = &patch.enrollment_url {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            warn!("Rejected settings update: {url} is not a valid enrollment URL");
+            return Err(WebError::Http(StatusCode::UNPROCESSABLE_ENTITY));
+        }
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/settings",
+    responses(
+        (status = 200, description = "Successfully retrieved current settings.", body = Settings),
+        (status = 401, description = "Unauthorized to view settings.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to view settings.", body = ApiResponse, example = json!({"msg": "requires privileged access"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn get_settings(session: SessionInfo, State(appstate): State<AppState>) -> ApiResult {
+    if !session.is_admin {
+        return Err(WebError::Forbidden("requires privileged access".into()));
+    }
+    let settings = Settings::find_current(&appstate.pool).await?;
+    Ok(ApiResponse::new(
+        serde_json::json!(settings),
+        axum::http::StatusCode::OK,
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/settings",
+    request_body = SettingsPatch,
+    responses(
+        (status = 200, description = "Successfully updated settings.", body = Settings),
+        (status = 422, description = "Invalid settings payload.", body = ApiResponse, example = json!({"msg": "Unprocessable Entity"})),
+        (status = 401, description = "Unauthorized to modify settings.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to modify settings.", body = ApiResponse, example = json!({"msg": "requires privileged access"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn update_settings(
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    axum::extract::Json(patch): axum::extract::Json<SettingsPatch>,
+) -> ApiResult {
+    if !session.is_admin {
+        return Err(WebError::Forbidden("requires privileged access".into()));
+    }
+    validate_settings_patch(&patch)?;
+
+    let mut settings = Settings::find_current(&appstate.pool).await?;
+    settings.apply_patch(patch);
+    settings.save(&appstate.pool).await?;
+
+    info!(
+        "User {} updated runtime configuration",
+        session.user.username
+    );
+    Ok(ApiResponse::new(
+        serde_json::json!(settings),
+        axum::http::StatusCode::OK,
+    ))
+}
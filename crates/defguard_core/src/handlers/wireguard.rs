@@ -7,13 +7,13 @@ use std::{
 
 use axum::{
     extract::{Json, Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     Extension,
 };
 use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
 use ipnetwork::IpNetwork;
 use serde_json::{json, Value};
-use sqlx::PgPool;
+use sqlx::{postgres::types::PgInterval, query, query_as, PgConnection, PgPool};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
@@ -23,24 +23,41 @@ use crate::{
     auth::{AdminRole, Claims, ClaimsType, SessionInfo},
     db::{
         models::{
+            auth_request::AuthRequest,
+            connection_event::ConnectionEvent,
             device::{
                 DeviceConfig, DeviceInfo, DeviceNetworkInfo, DeviceType, ModifyDevice,
                 WireguardNetworkDevice,
             },
+            device_auth_request::DeviceAuthRequest,
+            device_command::{DeviceCommand, DeviceCommandKind},
+            device_list_version::DeviceListVersion,
+            ip_reservation::IpReservation,
+            network_permission::{NetworkPermission, NetworkPermissionKind},
+            settings::Settings,
             wireguard::{
                 networks_stats, DateTimeAggregation, MappedDevice, WireguardDeviceStatsRow,
                 WireguardNetworkInfo, WireguardNetworkStats, WireguardUserStatsRow,
+                WIREGUARD_MAX_HANDSHAKE,
             },
         },
-        AddDevice, Device, GatewayEvent, Id, WireguardNetwork,
+        AddDevice, Device, GatewayEvent, Id, NoId, WireguardNetwork,
+    },
+    enterprise::{
+        handlers::CanManageDevices,
+        limits::update_counts,
+        network_authz::{
+            ensure_can_manage_device_networks, ManageNetworkRole, ViewNetworkStatsRole,
+        },
     },
-    enterprise::{handlers::CanManageDevices, limits::update_counts},
     events::{ApiEvent, ApiEventType, ApiRequestContext},
     grpc::GatewayMap,
     handlers::mail::send_new_device_added_email,
     server_config,
+    step_up::{enforce_step_up_auth, StepUpOutcome},
     templates::TemplateLocation,
     wg_config::{parse_wireguard_config, ImportedDevice},
+    ws::{WsMessage, WsUpdateType},
     AsCsv,
 };
 
@@ -66,6 +83,61 @@ pub(crate) fn parse_network_address_list(ips: &str) -> Vec<IpNetwork> {
         .collect()
 }
 
+/// A client-provided `timestamp` older than this is treated as stale/replayed
+/// rather than merely racing a concurrent edit.
+const DEVICE_LIST_TIMESTAMP_VALID_FOR: TimeDelta = TimeDelta::minutes(5);
+
+struct DeviceListTimestampRow {
+    device_list_timestamp: Option<NaiveDateTime>,
+}
+
+/// Validates `client_timestamp` (the client's view of its device list's
+/// version) against the version stored for `user_id`, then stamps and
+/// returns a fresh one, all within `conn` so the version and the device
+/// mutation it guards land in the same transaction.
+///
+/// A missing `client_timestamp` skips validation (the caller doesn't support
+/// optimistic concurrency) but the write is still stamped. Otherwise the
+/// timestamp must be strictly newer than the stored one and no older than
+/// [`DEVICE_LIST_TIMESTAMP_VALID_FOR`], or the mutation is rejected.
+///
+/// The row is locked with `FOR UPDATE` before the comparison so two
+/// concurrent mutations racing on the same stale `client_timestamp` can't
+/// both read the same `stored` value and both pass validation.
+async fn validate_and_bump_device_list_timestamp(
+    conn: &mut PgConnection,
+    user_id: Id,
+    client_timestamp: Option<NaiveDateTime>,
+) -> Result<NaiveDateTime, WebError> {
+    let stored = query_as!(
+        DeviceListTimestampRow,
+        "SELECT device_list_timestamp FROM \"user\" WHERE id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .and_then(|row| row.device_list_timestamp);
+
+    if let Some(client_timestamp) = client_timestamp {
+        let is_stale = stored.is_some_and(|stored| client_timestamp <= stored)
+            || Utc::now().naive_utc() - client_timestamp > DEVICE_LIST_TIMESTAMP_VALID_FOR;
+        if is_stale {
+            return Err(WebError::Http(StatusCode::CONFLICT));
+        }
+    }
+
+    let new_timestamp = Utc::now().naive_utc();
+    query!(
+        "UPDATE \"user\" SET device_list_timestamp = $2 WHERE id = $1",
+        user_id,
+        new_timestamp,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(new_timestamp)
+}
+
 #[derive(Deserialize, Serialize, ToSchema)]
 pub struct WireguardNetworkData {
     pub name: String,
@@ -201,7 +273,7 @@ async fn find_network(id: Id, pool: &PgPool) -> Result<WireguardNetwork<Id>, Web
     )
 )]
 pub(crate) async fn modify_network(
-    _role: AdminRole,
+    _role: ManageNetworkRole,
     Path(network_id): Path<i64>,
     State(appstate): State<AppState>,
     session: SessionInfo,
@@ -233,6 +305,7 @@ pub(crate) async fn modify_network(
         .set_allowed_groups(&mut transaction, data.allowed_groups)
         .await?;
     let _events = network.sync_allowed_devices(&mut transaction, None).await?;
+    DeviceListVersion::append_new_version(&mut transaction, network.id).await?;
 
     let peers = network.get_peers(&mut *transaction).await?;
     let maybe_firewall_config = network.try_get_firewall_config(&mut transaction).await?;
@@ -272,7 +345,7 @@ pub(crate) async fn modify_network(
     )
 )]
 pub(crate) async fn delete_network(
-    _role: AdminRole,
+    _role: ManageNetworkRole,
     Path(network_id): Path<i64>,
     State(appstate): State<AppState>,
     session: SessionInfo,
@@ -302,6 +375,266 @@ pub(crate) async fn delete_network(
     Ok(ApiResponse::default())
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct AddNetworkPermission {
+    pub user_id: Option<Id>,
+    pub group_id: Option<Id>,
+    pub kind: NetworkPermissionKind,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/network/{network_id}/permission",
+    params(
+        ("network_id" = i64, description = "Id of the network.")
+    ),
+    responses(
+        (status = 200, description = "List of permission grants on the network.", body = [NetworkPermission]),
+        (status = 401, description = "Unauthorized to list network permissions.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to manage this network.", body = ApiResponse, example = json!({"msg": "access denied"})),
+        (status = 500, description = "Unable to list network permissions.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn list_network_permissions(
+    _role: ManageNetworkRole,
+    Path(network_id): Path<Id>,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    let permissions = NetworkPermission::all_for_network(&appstate.pool, network_id).await?;
+    Ok(ApiResponse {
+        json: json!(permissions),
+        status: StatusCode::OK,
+    })
+}
+
+/// Grant a per-network permission to a user or group
+///
+/// Lets a global admin, or a user who already holds `ManageNetwork` on this
+/// network, delegate a scoped capability to someone else instead of handing
+/// out the blanket [`AdminRole`]. Exactly one of `user_id`/`group_id` must be
+/// set.
+#[utoipa::path(
+    post,
+    path = "/api/v1/network/{network_id}/permission",
+    params(
+        ("network_id" = i64, description = "Id of the network.")
+    ),
+    request_body = AddNetworkPermission,
+    responses(
+        (status = 201, description = "Successfully granted a network permission.", body = NetworkPermission),
+        (status = 400, description = "Exactly one of user_id/group_id must be set.", body = ApiResponse, example = json!({"msg": "exactly one of user_id or group_id must be set"})),
+        (status = 401, description = "Unauthorized to grant network permissions.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to manage this network.", body = ApiResponse, example = json!({"msg": "access denied"})),
+        (status = 500, description = "Unable to grant the network permission.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn add_network_permission(
+    _role: ManageNetworkRole,
+    Path(network_id): Path<Id>,
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Json(data): Json<AddNetworkPermission>,
+) -> ApiResult {
+    if data.user_id.is_some() == data.group_id.is_some() {
+        return Err(WebError::BadRequest(
+            "exactly one of user_id or group_id must be set".into(),
+        ));
+    }
+
+    let permission = NetworkPermission {
+        id: NoId,
+        network_id,
+        user_id: data.user_id,
+        group_id: data.group_id,
+        kind: data.kind,
+    }
+    .save(&appstate.pool)
+    .await?;
+
+    info!(
+        "User {} granted {:?} permission on network {network_id}",
+        session.user.username, permission.kind
+    );
+
+    Ok(ApiResponse {
+        json: json!(permission),
+        status: StatusCode::CREATED,
+    })
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/network/{network_id}/permission/{permission_id}",
+    params(
+        ("network_id" = i64, description = "Id of the network."),
+        ("permission_id" = i64, description = "Id of the permission grant.")
+    ),
+    responses(
+        (status = 200, description = "Successfully revoked a network permission.", body = ApiResponse),
+        (status = 401, description = "Unauthorized to revoke network permissions.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to manage this network.", body = ApiResponse, example = json!({"msg": "access denied"})),
+        (status = 404, description = "Permission grant not found.", body = ApiResponse, example = json!({"msg": "permission <id> not found"})),
+        (status = 500, description = "Unable to revoke the network permission.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn delete_network_permission(
+    _role: ManageNetworkRole,
+    Path((network_id, permission_id)): Path<(Id, Id)>,
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    let permission = NetworkPermission::find_by_id(&appstate.pool, permission_id)
+        .await?
+        .filter(|permission| permission.network_id == network_id)
+        .ok_or_else(|| WebError::ObjectNotFound(format!("permission {permission_id} not found")))?;
+
+    permission.delete(&appstate.pool).await?;
+
+    info!(
+        "User {} revoked permission {permission_id} on network {network_id}",
+        session.user.username,
+    );
+
+    Ok(ApiResponse::default())
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AddIpReservation {
+    #[schema(value_type = String)]
+    pub cidr: IpNetwork,
+    pub label: String,
+    pub device_id: Option<Id>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/network/{network_id}/reservation",
+    params(
+        ("network_id" = i64, description = "Id of the network.")
+    ),
+    responses(
+        (status = 200, description = "List of reservations on the network.", body = [IpReservation]),
+        (status = 401, description = "Unauthorized to list network reservations.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to manage this network.", body = ApiResponse, example = json!({"msg": "access denied"})),
+        (status = 500, description = "Unable to list network reservations.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn list_ip_reservations(
+    _role: ManageNetworkRole,
+    Path(network_id): Path<Id>,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    let reservations = IpReservation::all_for_network(&appstate.pool, network_id).await?;
+    Ok(ApiResponse {
+        json: json!(reservations),
+        status: StatusCode::OK,
+    })
+}
+
+/// Reserve a sub-range, or lease a single address to a device
+///
+/// Lets an admin (or a delegate with `ManageNetwork`) carve out a sub-range
+/// for infrastructure that isn't a defguard device, or permanently bind one
+/// address to a named device, so [`Device::assign_next_network_ip`] never
+/// hands either out to a different device.
+#[utoipa::path(
+    post,
+    path = "/api/v1/network/{network_id}/reservation",
+    params(
+        ("network_id" = i64, description = "Id of the network.")
+    ),
+    request_body = AddIpReservation,
+    responses(
+        (status = 201, description = "Successfully added the reservation.", body = IpReservation),
+        (status = 401, description = "Unauthorized to add network reservations.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to manage this network.", body = ApiResponse, example = json!({"msg": "access denied"})),
+        (status = 500, description = "Unable to add the reservation.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn add_ip_reservation(
+    _role: ManageNetworkRole,
+    Path(network_id): Path<Id>,
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Json(data): Json<AddIpReservation>,
+) -> ApiResult {
+    let reservation = IpReservation::new(network_id, data.cidr, data.label, data.device_id)
+        .save(&appstate.pool)
+        .await?;
+
+    info!(
+        "User {} added IP reservation {} on network {network_id}",
+        session.user.username, reservation.cidr,
+    );
+
+    Ok(ApiResponse {
+        json: json!(reservation),
+        status: StatusCode::CREATED,
+    })
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/network/{network_id}/reservation/{reservation_id}",
+    params(
+        ("network_id" = i64, description = "Id of the network."),
+        ("reservation_id" = i64, description = "Id of the reservation.")
+    ),
+    responses(
+        (status = 200, description = "Successfully deleted the reservation.", body = ApiResponse),
+        (status = 401, description = "Unauthorized to delete network reservations.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to manage this network.", body = ApiResponse, example = json!({"msg": "access denied"})),
+        (status = 404, description = "Reservation not found.", body = ApiResponse, example = json!({"msg": "reservation <id> not found"})),
+        (status = 500, description = "Unable to delete the reservation.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn delete_ip_reservation(
+    _role: ManageNetworkRole,
+    Path((network_id, reservation_id)): Path<(Id, Id)>,
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    let reservation = IpReservation::find_by_id(&appstate.pool, reservation_id)
+        .await?
+        .filter(|reservation| reservation.wireguard_network_id == network_id)
+        .ok_or_else(|| {
+            WebError::ObjectNotFound(format!("reservation {reservation_id} not found"))
+        })?;
+
+    reservation.delete(&appstate.pool).await?;
+
+    info!(
+        "User {} deleted IP reservation {reservation_id} on network {network_id}",
+        session.user.username,
+    );
+
+    Ok(ApiResponse::default())
+}
+
 #[utoipa::path(
     get,
     path = "/api/v1/network",
@@ -317,7 +650,7 @@ pub(crate) async fn delete_network(
     )
 )]
 pub(crate) async fn list_networks(
-    _role: AdminRole,
+    session: SessionInfo,
     State(appstate): State<AppState>,
     Extension(gateway_state): Extension<Arc<Mutex<GatewayMap>>>,
 ) -> ApiResult {
@@ -325,6 +658,19 @@ pub(crate) async fn list_networks(
     let mut network_info = Vec::new();
     let networks = WireguardNetwork::all(&appstate.pool).await?;
 
+    // global admins see every network; everyone else only sees networks
+    // they've been granted a permission on
+    let networks = if session.is_admin {
+        networks
+    } else {
+        let visible_ids =
+            NetworkPermission::network_ids_for_user(&appstate.pool, session.user.id).await?;
+        networks
+            .into_iter()
+            .filter(|network| visible_ids.contains(&network.id))
+            .collect()
+    };
+
     for network in networks {
         let network_id = network.id;
         let allowed_groups = network.fetch_allowed_groups(&appstate.pool).await?;
@@ -365,7 +711,7 @@ pub(crate) async fn list_networks(
 )]
 pub(crate) async fn network_details(
     Path(network_id): Path<i64>,
-    _role: AdminRole,
+    _role: ViewNetworkStatsRole,
     State(appstate): State<AppState>,
     Extension(gateway_state): Extension<Arc<Mutex<GatewayMap>>>,
 ) -> ApiResult {
@@ -391,11 +737,328 @@ pub(crate) async fn network_details(
         None => ApiResponse {
             json: Value::Null,
             status: StatusCode::NOT_FOUND,
-        },
+        },
+    };
+    debug!("Displayed network details for network {network_id}");
+
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/network/{network_id}/device_list",
+    responses(
+        (status = 200, description = "Latest signed device list", body = DeviceListVersion),
+        (status = 401, description = "Unauthorized to get device list.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to get the device list.", body = ApiResponse, example = json!({"msg": "access denied"})),
+        (status = 404, description = "Network has no device list yet", body = ApiResponse, example = json!({"msg": "network not found"})),
+        (status = 500, description = "Unable to get device list.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn get_device_list(
+    Path(network_id): Path<i64>,
+    _role: ViewNetworkStatsRole,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    debug!("Fetching latest signed device list for network {network_id}");
+    let latest = DeviceListVersion::latest_for_network(&appstate.pool, network_id).await?;
+    let response = match latest {
+        Some(version) => ApiResponse {
+            json: json!(version),
+            status: StatusCode::OK,
+        },
+        None => ApiResponse {
+            json: Value::Null,
+            status: StatusCode::NOT_FOUND,
+        },
+    };
+    debug!("Fetched latest signed device list for network {network_id}");
+
+    Ok(response)
+}
+
+/// Outcome of walking a network's signed device-list chain. `broken_at` is
+/// the first version number whose link or signature didn't check out, if
+/// any - see [`DeviceListVersion::verify_chain`].
+#[derive(Serialize, ToSchema)]
+pub struct DeviceListChainVerification {
+    pub broken_at: Option<i64>,
+}
+
+/// Verify a network's signed device-list chain
+///
+/// Walks every recorded [`DeviceListVersion`] for `network_id` and confirms
+/// each one's `prev_version` link and HMAC signature, so gateways or
+/// auditors can detect an unauthorized out-of-band edit to network
+/// membership (e.g. a row patched directly in the database) instead of
+/// trusting the chain blindly.
+#[utoipa::path(
+    get,
+    path = "/api/v1/network/{network_id}/device_list/verify",
+    params(
+        ("network_id" = i64, description = "Id of network to verify the device list chain for.")
+    ),
+    responses(
+        (status = 200, description = "Chain verification result.", body = DeviceListChainVerification),
+        (status = 401, description = "Unauthorized to verify the device list.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to verify the device list.", body = ApiResponse, example = json!({"msg": "access denied"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn verify_device_list(
+    Path(network_id): Path<i64>,
+    _role: ViewNetworkStatsRole,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    debug!("Verifying signed device list chain for network {network_id}");
+    let broken_at = DeviceListVersion::verify_chain(&appstate.pool, network_id).await?;
+    if let Some(broken_at) = broken_at {
+        warn!("Device list chain for network {network_id} is broken at version {broken_at}");
+    } else {
+        debug!("Device list chain for network {network_id} verified intact");
+    }
+
+    Ok(ApiResponse {
+        json: json!(DeviceListChainVerification { broken_at }),
+        status: StatusCode::OK,
+    })
+}
+
+/// How many recent [`ConnectionEvent`]s to return from
+/// [`get_connection_history`] - enough to eyeball a flapping peer without
+/// dumping its entire history.
+const CONNECTION_HISTORY_LIMIT: i64 = 20;
+
+/// [`get_connection_history`]'s response: the raw recent events alongside
+/// their rolling success rate, so a caller doesn't have to recompute
+/// [`ConnectionEvent::success_rate`] itself from the returned list.
+#[derive(Serialize, ToSchema)]
+pub struct ConnectionHistoryResponse {
+    pub history: Vec<ConnectionEvent>,
+    pub success_rate: Option<f64>,
+}
+
+/// A device/network pair's recent connection history
+///
+/// Returns the most recent connection attempts recorded for `device_id` on
+/// `network_id`, freshest first, plus their rolling success rate - see
+/// [`ConnectionEvent`].
+///
+/// # Returns
+/// Returns a list of `ConnectionEvent` objects or `WebError` object if error occurs.
+#[utoipa::path(
+    get,
+    path = "/api/v1/device/{device_id}/network/{network_id}/connection_history",
+    params(
+        ("device_id" = i64, description = "Id of the device."),
+        ("network_id" = i64, description = "Id of the network.")
+    ),
+    responses(
+        (status = 200, description = "Recent connection history.", body = ConnectionHistoryResponse),
+        (status = 401, description = "Unauthorized to view connection history.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 404, description = "Device not found.", body = ApiResponse, example = json!({"msg": "device id <id> not found"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn get_connection_history(
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Path((device_id, network_id)): Path<(i64, i64)>,
+) -> ApiResult {
+    let device = device_for_admin_or_self(&appstate.pool, &session, device_id).await?;
+    let history =
+        ConnectionEvent::recent(&appstate.pool, device.id, network_id, CONNECTION_HISTORY_LIMIT)
+            .await?;
+    let success_rate = ConnectionEvent::success_rate(&history);
+
+    Ok(ApiResponse {
+        json: json!(ConnectionHistoryResponse {
+            history,
+            success_rate,
+        }),
+        status: StatusCode::OK,
+    })
+}
+
+/// How many of a pair's most recent events [`list_flapping_devices`]
+/// requires on record before judging it repeatedly failing - mirrors
+/// [`CONNECTION_HISTORY_LIMIT`] but smaller, since a handful of consecutive
+/// failures is already actionable.
+const FLAPPING_SAMPLE_SIZE: i64 = 5;
+/// A device/network pair with a rolling success rate at or below this,
+/// over [`FLAPPING_SAMPLE_SIZE`] events, is surfaced by
+/// [`list_flapping_devices`].
+const FLAPPING_THRESHOLD: f64 = 0.5;
+
+/// A repeatedly-failing device/network pair, as surfaced by
+/// [`list_flapping_devices`].
+#[derive(Serialize, ToSchema)]
+pub struct FlappingDevice {
+    pub device_id: Id,
+    pub network_id: Id,
+}
+
+/// List devices repeatedly failing to connect
+///
+/// Surfaces device/network pairs whose last [`FLAPPING_SAMPLE_SIZE`]
+/// connection attempts have a success rate at or below
+/// [`FLAPPING_THRESHOLD`], for an admin dashboard or alert - see
+/// [`ConnectionEvent::repeatedly_failing`].
+#[utoipa::path(
+    get,
+    path = "/api/v1/device/flapping",
+    responses(
+        (status = 200, description = "Repeatedly-failing device/network pairs.", body = [FlappingDevice]),
+        (status = 401, description = "Unauthorized to list flapping devices.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to list flapping devices.", body = ApiResponse, example = json!({"msg": "requires privileged access"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn list_flapping_devices(
+    _role: AdminRole,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    let pairs = ConnectionEvent::repeatedly_failing(
+        &appstate.pool,
+        FLAPPING_SAMPLE_SIZE,
+        FLAPPING_THRESHOLD,
+    )
+    .await?;
+    let flapping: Vec<FlappingDevice> = pairs
+        .into_iter()
+        .map(|(device_id, network_id)| FlappingDevice {
+            device_id,
+            network_id,
+        })
+        .collect();
+
+    Ok(ApiResponse {
+        json: json!(flapping),
+        status: StatusCode::OK,
+    })
+}
+
+/// A single device's place in a network's effective routing table.
+#[derive(Serialize, ToSchema)]
+pub struct DeviceRoute {
+    device_id: Id,
+    device_name: String,
+    assigned_ips: Vec<IpAddr>,
+    connected: bool,
+    last_handshake: Option<NaiveDateTime>,
+}
+
+/// The effective routing table for a network: which `allowed_ips` route to
+/// which device, whether that device is currently reachable, and whether any
+/// of the network's configured CIDRs overlap with each other.
+#[derive(Serialize, ToSchema)]
+pub struct NetworkRoutesInfo {
+    network_id: Id,
+    allowed_ips: Vec<IpNetwork>,
+    routes: Vec<DeviceRoute>,
+    address_collisions: Vec<(IpNetwork, IpNetwork)>,
+}
+
+/// Returns every pair of networks in `networks` whose address ranges overlap,
+/// e.g. two `allowed_ips` entries that were meant to be disjoint but collide.
+fn detect_collisions(networks: &[IpNetwork]) -> Vec<(IpNetwork, IpNetwork)> {
+    let mut collisions = Vec::new();
+    for (index, a) in networks.iter().enumerate() {
+        for b in &networks[index + 1..] {
+            if a.contains(b.network()) || b.contains(a.network()) {
+                collisions.push((*a, *b));
+            }
+        }
+    }
+
+    collisions
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/network/{network_id}/routes",
+    responses(
+        (status = 200, description = "Effective routing table for a network", body = NetworkRoutesInfo),
+        (status = 401, description = "Unauthorized to get network routes.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to get network routes.", body = ApiResponse, example = json!({"msg": "access denied"})),
+        (status = 404, description = "Network not found", body = ApiResponse, example = json!({"msg": "network not found"})),
+        (status = 500, description = "Unable to get network routes.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn network_routes(
+    Path(network_id): Path<i64>,
+    _role: ViewNetworkStatsRole,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    debug!("Computing effective routing table for network {network_id}");
+    let Some(network) = WireguardNetwork::find_by_id(&appstate.pool, network_id).await? else {
+        return Ok(ApiResponse {
+            json: Value::Null,
+            status: StatusCode::NOT_FOUND,
+        });
     };
-    debug!("Displayed network details for network {network_id}");
 
-    Ok(response)
+    let rows = query!(
+        "WITH stats AS ( \
+            SELECT DISTINCT ON (device_id) device_id, latest_handshake \
+            FROM wireguard_peer_stats WHERE network = $1 \
+            ORDER BY device_id, collected_at DESC \
+        ) \
+        SELECT d.id device_id, d.name device_name, \
+            wnd.wireguard_ips \"assigned_ips: Vec<IpAddr>\", \
+            stats.latest_handshake \"last_handshake?\", \
+            COALESCE((NOW() - stats.latest_handshake) < $2, FALSE) \"connected!\" \
+        FROM wireguard_network_device wnd \
+        JOIN device d ON d.id = wnd.device_id \
+        LEFT JOIN stats ON stats.device_id = wnd.device_id \
+        WHERE wnd.wireguard_network_id = $1",
+        network_id,
+        PgInterval::try_from(WIREGUARD_MAX_HANDSHAKE).unwrap(),
+    )
+    .fetch_all(&appstate.pool)
+    .await?;
+
+    let routes = rows
+        .into_iter()
+        .map(|row| DeviceRoute {
+            device_id: row.device_id,
+            device_name: row.device_name,
+            assigned_ips: row.assigned_ips,
+            connected: row.connected,
+            last_handshake: row.last_handshake,
+        })
+        .collect();
+
+    let address_collisions = detect_collisions(&network.allowed_ips);
+
+    debug!("Computed effective routing table for network {network_id}");
+
+    Ok(ApiResponse {
+        json: json!(NetworkRoutesInfo {
+            network_id,
+            allowed_ips: network.allowed_ips.clone(),
+            routes,
+            address_collisions,
+        }),
+        status: StatusCode::OK,
+    })
 }
 
 /// Returns state of gateways in a given network
@@ -504,6 +1167,8 @@ pub(crate) async fn import_network(
     appstate.send_multiple_wireguard_events(gateway_events);
     debug!("Assigned IPs in imported network for remaining existing devices");
 
+    DeviceListVersion::append_new_version(&mut transaction, network.id).await?;
+
     transaction.commit().await?;
 
     info!("Imported network {network} with {} devices", devices.len());
@@ -549,6 +1214,7 @@ pub(crate) async fn add_user_devices(
             .handle_mapped_devices(&mut transaction, mapped_devices)
             .await?;
         appstate.send_multiple_wireguard_events(events);
+        DeviceListVersion::append_new_version(&mut transaction, network.id).await?;
         transaction.commit().await?;
 
         info!(
@@ -574,6 +1240,10 @@ pub(crate) async fn add_user_devices(
 pub struct AddDeviceResult {
     configs: Vec<DeviceConfig>,
     device: Device<Id>,
+    /// The device owner's new device-list version, if one was computed; lets
+    /// clients detect when their cached device list is out of date.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_list_timestamp: Option<NaiveDateTime>,
 }
 
 /// Add device
@@ -680,8 +1350,32 @@ pub(crate) async fn add_device(
         )));
     }
 
+    // if the instance requires admin approval for new devices, park the
+    // request instead of provisioning the device right away
+    let settings = Settings::find_current(&appstate.pool).await?;
+    if settings.device_approval_required {
+        let request = AuthRequest::new(
+            user.id,
+            device_name.clone(),
+            add_device.wireguard_pubkey,
+            context.ip.to_string(),
+            DeviceType::User,
+        )
+        .save(&appstate.pool)
+        .await?;
+
+        info!(
+            "Parked device {device_name} for user {username} pending admin approval (request {})",
+            request.uuid
+        );
+        return Ok(ApiResponse::new(json!(request), StatusCode::ACCEPTED));
+    }
+
     // save the device
     let mut transaction = appstate.pool.begin().await?;
+    let device_list_timestamp =
+        validate_and_bump_device_list_timestamp(&mut transaction, user.id, add_device.timestamp)
+            .await?;
     let device = Device::new(
         add_device.name,
         add_device.wireguard_pubkey,
@@ -719,6 +1413,7 @@ pub(crate) async fn add_device(
                 ));
             }
         }
+        DeviceListVersion::append_new_version(&mut transaction, location_id).await?;
     }
 
     // add peer on relevant gateways
@@ -766,23 +1461,415 @@ pub(crate) async fn add_device(
     let device_name = device.name.clone();
 
     let device_id = device.id;
-    let result = AddDeviceResult { configs, device };
+    let result = AddDeviceResult {
+        configs,
+        device,
+        device_list_timestamp: Some(device_list_timestamp),
+    };
 
     update_counts(&appstate.pool).await?;
 
-    appstate.emit_event(ApiEvent {
-        context,
-        event: ApiEventType::UserDeviceAdded {
-            device_id,
-            owner: username,
-            device_name,
-        },
-    })?;
+    appstate.ws_hub.broadcast(
+        WsMessage::new(WsUpdateType::DeviceUpdate, Some(device_id), None),
+        Some(user.id),
+    );
+
+    appstate.emit_event(ApiEvent {
+        context,
+        event: ApiEventType::UserDeviceAdded {
+            device_id,
+            owner: username,
+            device_name,
+        },
+    })?;
+
+    Ok(ApiResponse {
+        json: json!(result),
+        status: StatusCode::CREATED,
+    })
+}
+
+/// List device-add requests that are still waiting on an admin decision.
+#[utoipa::path(
+    get,
+    path = "/api/v1/device/pending",
+    responses(
+        (status = 200, description = "Successfully retrieved pending device requests.", body = [AuthRequest]),
+        (status = 401, description = "Unauthorized.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to view pending device requests.", body = ApiResponse, example = json!({"msg": "access denied"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn list_pending_devices(
+    _role: AdminRole,
+    State(appstate): State<AppState>,
+) -> ApiResult {
+    let requests = AuthRequest::all_pending(&appstate.pool).await?;
+    Ok(ApiResponse::new(json!(requests), StatusCode::OK))
+}
+
+async fn find_pending_request(
+    pool: &sqlx::PgPool,
+    uuid: Uuid,
+) -> Result<crate::db::models::auth_request::AuthRequest<Id>, WebError> {
+    let request = AuthRequest::find_by_uuid(pool, uuid)
+        .await?
+        .ok_or_else(|| WebError::ObjectNotFound(format!("Device request {uuid} not found")))?;
+    if !request.is_pending() {
+        return Err(WebError::BadRequest(format!(
+            "Device request {uuid} was already decided"
+        )));
+    }
+    Ok(request)
+}
+
+/// Approves a pending device-add request, provisioning the device exactly
+/// like [`add_device`] would have if approval mode were off.
+#[utoipa::path(
+    post,
+    path = "/api/v1/device/pending/{uuid}/approve",
+    responses(
+        (status = 201, description = "Successfully approved and provisioned the device.", body = AddDeviceResult),
+        (status = 401, description = "Unauthorized.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to approve device requests.", body = ApiResponse, example = json!({"msg": "access denied"})),
+        (status = 404, description = "Device request not found.", body = ApiResponse, example = json!({"msg": "Device request not found"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn approve_device(
+    _role: AdminRole,
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Path(uuid): Path<Uuid>,
+) -> ApiResult {
+    let mut request = find_pending_request(&appstate.pool, uuid).await?;
+    let user = crate::db::User::find_by_id(&appstate.pool, request.user_id)
+        .await?
+        .ok_or_else(|| WebError::ObjectNotFound(format!("User {} not found", request.user_id)))?;
+
+    let mut transaction = appstate.pool.begin().await?;
+    let device = Device::new(
+        request.device_name.clone(),
+        request.wireguard_pubkey.clone(),
+        user.id,
+        request.device_type.clone(),
+        None,
+        true,
+    )
+    .save(&mut *transaction)
+    .await?;
+
+    let (network_info, configs) = device.add_to_all_networks(&mut transaction).await?;
+
+    appstate.send_wireguard_event(GatewayEvent::DeviceCreated(DeviceInfo {
+        device: device.clone(),
+        network_info,
+    }));
+
+    request.mark_decided(true);
+    request.save(&mut *transaction).await?;
+
+    transaction.commit().await?;
+
+    let template_locations: Vec<TemplateLocation> = configs
+        .iter()
+        .map(|c| TemplateLocation {
+            name: c.network_name.clone(),
+            assigned_ips: c.address.as_csv(),
+        })
+        .collect();
+    send_new_device_added_email(
+        &device.name,
+        &device.wireguard_pubkey,
+        &template_locations,
+        &user.email,
+        &appstate.mail_tx,
+        Some(&request.request_ip),
+        None,
+    )?;
+
+    info!(
+        "Admin {} approved device request {uuid} for user {}",
+        session.user.username, user.username
+    );
+
+    Ok(ApiResponse::new(
+        json!(AddDeviceResult {
+            configs,
+            device,
+            device_list_timestamp: None,
+        }),
+        StatusCode::CREATED,
+    ))
+}
+
+/// Rejects a pending device-add request without provisioning anything.
+#[utoipa::path(
+    post,
+    path = "/api/v1/device/pending/{uuid}/reject",
+    responses(
+        (status = 200, description = "Successfully rejected the device request.", body = ApiResponse),
+        (status = 401, description = "Unauthorized.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to reject device requests.", body = ApiResponse, example = json!({"msg": "access denied"})),
+        (status = 404, description = "Device request not found.", body = ApiResponse, example = json!({"msg": "Device request not found"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn reject_device(
+    _role: AdminRole,
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Path(uuid): Path<Uuid>,
+) -> ApiResult {
+    let mut request = find_pending_request(&appstate.pool, uuid).await?;
+    request.mark_decided(false);
+    request.save(&appstate.pool).await?;
+
+    info!(
+        "Admin {} rejected device request {uuid}",
+        session.user.username
+    );
+
+    Ok(ApiResponse::new(
+        json!({ "msg": "Device request rejected" }),
+        StatusCode::OK,
+    ))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RequestDeviceAuth {
+    pub wireguard_pubkey: String,
+    pub device_info: Option<String>,
+}
+
+/// Starts a "login with device"-style enrollment: a new device asks to join
+/// and is parked until it's approved from an already-trusted device or by an
+/// admin. Unlike [`add_device`], this endpoint requires no session, so it
+/// does not know which user the device belongs to yet - that's decided by
+/// whoever approves it.
+#[utoipa::path(
+    post,
+    path = "/api/v1/device/auth-request",
+    responses(
+        (status = 201, description = "Device auth request created.", body = DeviceAuthRequest, example = json!({"access_code": "aB3dE9fG"})),
+        (status = 400, description = "Invalid pubkey or a device with this pubkey already exists.", body = ApiResponse, example = json!({"msg": "Invalid key"})),
+        (status = 500, description = "Unable to create device auth request.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    )
+)]
+pub(crate) async fn request_device_auth(
+    context: ApiRequestContext,
+    State(appstate): State<AppState>,
+    Json(data): Json<RequestDeviceAuth>,
+) -> ApiResult {
+    Device::validate_pubkey(&data.wireguard_pubkey).map_err(WebError::PubkeyValidation)?;
+    if Device::find_by_pubkey(&appstate.pool, &data.wireguard_pubkey)
+        .await?
+        .is_some()
+    {
+        return Err(WebError::PubkeyExists(format!(
+            "A device with pubkey {} already exists",
+            data.wireguard_pubkey
+        )));
+    }
+
+    let request = DeviceAuthRequest::new(
+        data.wireguard_pubkey,
+        context.ip.to_string(),
+        data.device_info,
+    )
+    .save(&appstate.pool)
+    .await?;
+
+    info!(
+        "New device auth request {} created from {}",
+        request.access_code, request.request_ip
+    );
+
+    Ok(ApiResponse::new(json!(request), StatusCode::CREATED))
+}
+
+/// Polled by the requesting device to learn whether it's been approved yet.
+#[utoipa::path(
+    get,
+    path = "/api/v1/device/auth-request/{access_code}",
+    responses(
+        (status = 200, description = "Request approved, config is ready.", body = ApiResponse),
+        (status = 202, description = "Still waiting for a decision.", body = ApiResponse, example = json!({"msg": "pending"})),
+        (status = 404, description = "No such request, or it expired.", body = ApiResponse, example = json!({"msg": "not found"})),
+        (status = 410, description = "Request was rejected.", body = ApiResponse, example = json!({"msg": "rejected"}))
+    )
+)]
+pub(crate) async fn poll_device_auth_status(
+    State(appstate): State<AppState>,
+    Path(access_code): Path<String>,
+) -> ApiResult {
+    let Some(request) = DeviceAuthRequest::find_by_access_code(&appstate.pool, &access_code).await?
+    else {
+        return Err(WebError::ObjectNotFound("Device auth request not found".into()));
+    };
+
+    if request.is_expired() && request.is_pending() {
+        return Err(WebError::ObjectNotFound(
+            "Device auth request expired".into(),
+        ));
+    }
+
+    match request.approved {
+        None => Ok(ApiResponse::new(
+            json!({ "msg": "pending" }),
+            StatusCode::ACCEPTED,
+        )),
+        Some(false) => Ok(ApiResponse::new(
+            json!({ "msg": "rejected" }),
+            StatusCode::GONE,
+        )),
+        Some(true) => {
+            let device_id = request
+                .device_id
+                .ok_or(WebError::Http(StatusCode::INTERNAL_SERVER_ERROR))?;
+            let device = Device::find_by_id(&appstate.pool, device_id)
+                .await?
+                .ok_or_else(|| WebError::ObjectNotFound(format!("Device {device_id} not found")))?;
+            let configs = request
+                .device_configs
+                .ok_or(WebError::Http(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+            Ok(ApiResponse::new(
+                json!({ "configs": configs, "device": device }),
+                StatusCode::OK,
+            ))
+        }
+    }
+}
+
+/// Rejects a pending device auth request without provisioning anything.
+#[utoipa::path(
+    post,
+    path = "/api/v1/device/auth-request/{access_code}/reject",
+    responses(
+        (status = 200, description = "Request rejected.", body = ApiResponse),
+        (status = 401, description = "Unauthorized.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 404, description = "No such request.", body = ApiResponse, example = json!({"msg": "not found"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn reject_device_auth_request(
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Path(access_code): Path<String>,
+) -> ApiResult {
+    let mut request = DeviceAuthRequest::find_by_access_code(&appstate.pool, &access_code)
+        .await?
+        .ok_or_else(|| WebError::ObjectNotFound("Device auth request not found".into()))?;
+
+    request.mark_rejected();
+    request.save(&appstate.pool).await?;
+
+    info!(
+        "User {} rejected device auth request {access_code}",
+        session.user.username
+    );
+
+    Ok(ApiResponse::new(
+        json!({ "msg": "Device auth request rejected" }),
+        StatusCode::OK,
+    ))
+}
+
+/// Approves a pending device auth request from the approving user's own
+/// session, provisioning the device exactly like [`add_device`] would.
+#[utoipa::path(
+    post,
+    path = "/api/v1/device/auth-request/{access_code}/approve",
+    responses(
+        (status = 201, description = "Device approved and provisioned.", body = AddDeviceResult),
+        (status = 400, description = "Request was already decided.", body = ApiResponse, example = json!({"msg": "already decided"})),
+        (status = 401, description = "Unauthorized.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 404, description = "No such request, or it expired.", body = ApiResponse, example = json!({"msg": "not found"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn approve_device_auth_request(
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Path(access_code): Path<String>,
+) -> ApiResult {
+    let mut request = DeviceAuthRequest::find_by_access_code(&appstate.pool, &access_code)
+        .await?
+        .ok_or_else(|| WebError::ObjectNotFound("Device auth request not found".into()))?;
+
+    if request.is_expired() {
+        return Err(WebError::ObjectNotFound(
+            "Device auth request expired".into(),
+        ));
+    }
+    if !request.is_pending() {
+        return Err(WebError::BadRequest(
+            "Device auth request was already decided".into(),
+        ));
+    }
+
+    let device_name = request
+        .device_info
+        .clone()
+        .unwrap_or_else(|| format!("device-{}", request.access_code));
+
+    let mut transaction = appstate.pool.begin().await?;
+    let device = Device::new(
+        device_name,
+        request.wireguard_pubkey.clone(),
+        session.user.id,
+        DeviceType::User,
+        None,
+        true,
+    )
+    .save(&mut *transaction)
+    .await?;
+
+    let (network_info, configs) = device.add_to_all_networks(&mut transaction).await?;
+    for network_info_item in &network_info {
+        DeviceListVersion::append_new_version(&mut transaction, network_info_item.network_id)
+            .await?;
+    }
+
+    appstate.send_wireguard_event(GatewayEvent::DeviceCreated(DeviceInfo {
+        device: device.clone(),
+        network_info,
+    }));
+
+    request.mark_approved(device.id, &json!(configs));
+    request.save(&mut *transaction).await?;
+
+    transaction.commit().await?;
+
+    info!(
+        "User {} approved device auth request {access_code}",
+        session.user.username
+    );
 
-    Ok(ApiResponse {
-        json: json!(result),
-        status: StatusCode::CREATED,
-    })
+    Ok(ApiResponse::new(
+        json!(AddDeviceResult {
+            configs,
+            device,
+            device_list_timestamp: None,
+        }),
+        StatusCode::CREATED,
+    ))
 }
 
 /// Modify device
@@ -802,18 +1889,22 @@ pub(crate) async fn add_device(
     ),
     request_body = ModifyDevice,
     responses(
-        (status = 200, description = "Successfully updated a device.", body = Device, example = json!(
+        (status = 200, description = "Successfully updated a device.", body = ApiResponse, example = json!(
             {
-                "id": 0,
-                "name": "name",
-                "wireguard_pubkey": "wireguard_pubkey",
-                "user_id": 0,
-                "created": "2024-07-10T10:25:43.231Z"
+                "device": {
+                    "id": 0,
+                    "name": "name",
+                    "wireguard_pubkey": "wireguard_pubkey",
+                    "user_id": 0,
+                    "created": "2024-07-10T10:25:43.231Z"
+                },
+                "device_list_timestamp": "2024-07-10T10:25:43.231Z"
             }
         )),
         (status = 400, description = "Bad request, no networks found or device with pubkey that you want to send with is a server's pubkey.", body = ApiResponse, example = json!({"msg": "device's pubkey must be different from server's pubkey"})),
-        (status = 401, description = "Unauthorized to update a device.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 401, description = "Unauthorized to update a device, or step-up re-authentication is required.", body = ApiResponse, example = json!({"msg": "Session is required"})),
         (status = 404, description = "Device not found.", body = ApiResponse, example = json!({"msg": "device id <id> not found"})),
+        (status = 409, description = "Client's device-list timestamp is stale or replayed.", body = ApiResponse, example = json!({"msg": "Conflict"})),
         (status = 500, description = "Cannot update a device.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
     ),
     security(
@@ -825,14 +1916,36 @@ pub(crate) async fn modify_device(
     _can_manage_devices: CanManageDevices,
     session: SessionInfo,
     context: ApiRequestContext,
+    headers: HeaderMap,
     Path(device_id): Path<i64>,
     State(appstate): State<AppState>,
     Json(data): Json<ModifyDevice>,
 ) -> ApiResult {
+    if let StepUpOutcome::Required(response) =
+        enforce_step_up_auth(&appstate.pool, &session, &headers).await?
+    {
+        return Ok(response);
+    }
+
     debug!("User {} updating device {device_id}", session.user.username);
     let mut device = device_for_admin_or_self(&appstate.pool, &session, device_id).await?;
     let networks = WireguardNetwork::all(&appstate.pool).await?;
 
+    let device_network_ids: Vec<_> = WireguardNetworkDevice::find_by_device(&appstate.pool, device.id)
+        .await?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|relation| relation.wireguard_network_id)
+        .collect();
+    ensure_can_manage_device_networks(
+        &appstate.pool,
+        session.user.id,
+        session.is_admin,
+        device.user_id == session.user.id,
+        &device_network_ids,
+    )
+    .await?;
+
     if networks.is_empty() {
         error!("Failed to update device {device_id}, no networks found");
         return Ok(ApiResponse {
@@ -852,13 +1965,29 @@ pub(crate) async fn modify_device(
         }
     }
 
+    let user_id = device.user_id;
+    let client_timestamp = data.timestamp;
+
     // update device info
     device.update_from(data);
 
     // clone to use later
     let device_name = device.name.clone();
 
-    device.save(&appstate.pool).await?;
+    let mut transaction = appstate.pool.begin().await?;
+    let device_list_timestamp = if device.device_type == DeviceType::User {
+        let timestamp = validate_and_bump_device_list_timestamp(
+            &mut transaction,
+            user_id,
+            client_timestamp,
+        )
+        .await?;
+        Some(timestamp)
+    } else {
+        None
+    };
+    device.save(&mut *transaction).await?;
+    transaction.commit().await?;
 
     // send update to gateway's
     let mut network_info = Vec::new();
@@ -882,6 +2011,11 @@ pub(crate) async fn modify_device(
 
     info!("User {} updated device {device_id}", session.user.username);
 
+    appstate.ws_hub.broadcast(
+        WsMessage::new(WsUpdateType::DeviceUpdate, Some(device.id), None),
+        Some(user_id),
+    );
+
     let owner = device.get_owner(&appstate.pool).await?.username;
     appstate.emit_event(ApiEvent {
         context,
@@ -893,7 +2027,7 @@ pub(crate) async fn modify_device(
     })?;
 
     Ok(ApiResponse {
-        json: json!(device),
+        json: json!({"device": device, "device_list_timestamp": device_list_timestamp}),
         status: StatusCode::OK,
     })
 }
@@ -941,6 +2075,13 @@ pub(crate) async fn get_device(
     })
 }
 
+#[derive(Deserialize)]
+pub struct DeleteDeviceParams {
+    /// Client's view of the user's device-list version. See
+    /// [`ModifyDevice::timestamp`].
+    timestamp: Option<NaiveDateTime>,
+}
+
 /// Delete device
 ///
 /// Delete user device and trigger new update in gateway server.
@@ -951,12 +2092,14 @@ pub(crate) async fn get_device(
     delete,
     path = "/api/v1/device/{device_id}",
     params(
-        ("device_id" = i64, description = "Id of device to update details.")
+        ("device_id" = i64, description = "Id of device to update details."),
+        ("timestamp" = Option<NaiveDateTime>, Query, description = "Client's view of the user's device-list version.")
     ),
     responses(
-        (status = 200, description = "Successfully deleted device."),
-        (status = 401, description = "Unauthorized to update a device.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 200, description = "Successfully deleted device.", body = ApiResponse, example = json!({"device_list_timestamp": "2024-07-10T10:25:43.231Z"})),
+        (status = 401, description = "Unauthorized to update a device, or step-up re-authentication is required.", body = ApiResponse, example = json!({"msg": "Session is required"})),
         (status = 404, description = "Device not found.", body = ApiResponse, example = json!({"msg": "device id <id> not found"})),
+        (status = 409, description = "Client's device-list timestamp is stale or replayed.", body = ApiResponse, example = json!({"msg": "Conflict"})),
         (status = 500, description = "Cannot update a device.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
     ),
     security(
@@ -968,9 +2111,17 @@ pub(crate) async fn delete_device(
     _can_manage_devices: CanManageDevices,
     session: SessionInfo,
     context: ApiRequestContext,
+    headers: HeaderMap,
     Path(device_id): Path<i64>,
+    Query(params): Query<DeleteDeviceParams>,
     State(appstate): State<AppState>,
 ) -> ApiResult {
+    if let StepUpOutcome::Required(response) =
+        enforce_step_up_auth(&appstate.pool, &session, &headers).await?
+    {
+        return Ok(response);
+    }
+
     // bind username to a variable for easier reference
     let username = &session.user.username;
 
@@ -979,11 +2130,36 @@ pub(crate) async fn delete_device(
 
     let device = device_for_admin_or_self(&mut *transaction, &session, device_id).await?;
 
+    let device_list_timestamp = if device.device_type == DeviceType::User {
+        let timestamp = validate_and_bump_device_list_timestamp(
+            &mut transaction,
+            device.user_id,
+            params.timestamp,
+        )
+        .await?;
+        Some(timestamp)
+    } else {
+        None
+    };
+
     let mut events = Vec::new();
 
     // prepare device info
     let device_info = DeviceInfo::from_device(&mut *transaction, device.clone()).await?;
 
+    ensure_can_manage_device_networks(
+        &appstate.pool,
+        session.user.id,
+        session.is_admin,
+        device.user_id == session.user.id,
+        &device_info
+            .network_info
+            .iter()
+            .map(|info| info.network_id)
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+
     // clone to use later
     let device_name = device.name.clone();
     let device_type = device.device_type.clone();
@@ -995,6 +2171,8 @@ pub(crate) async fn delete_device(
 
     // prepare firewall update for affected networks if ACL & enterprise features are enabled
     for info in &device_info.network_info {
+        DeviceListVersion::append_new_version(&mut transaction, info.network_id).await?;
+
         if let Some(location) =
             WireguardNetwork::find_by_id(&mut *transaction, info.network_id).await?
         {
@@ -1016,6 +2194,11 @@ pub(crate) async fn delete_device(
     // send generated gateway events
     appstate.send_multiple_wireguard_events(events);
 
+    appstate.ws_hub.broadcast(
+        WsMessage::new(WsUpdateType::DeviceUpdate, Some(device_id), None),
+        Some(device_info.device.user_id),
+    );
+
     // Emit event specific to the device type.
     match device_type {
         DeviceType::User => {
@@ -1059,6 +2242,178 @@ pub(crate) async fn delete_device(
     transaction.commit().await?;
     info!("User {username} deleted device {device_id}");
 
+    Ok(ApiResponse {
+        json: json!({"device_list_timestamp": device_list_timestamp}),
+        status: StatusCode::OK,
+    })
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeleteDevices {
+    device_ids: Vec<Id>,
+}
+
+/// Bulk delete devices
+///
+/// Delete multiple devices in a single transaction, recomputing each
+/// affected location's firewall config exactly once instead of once per
+/// device. Intended for decommissioning a user or cleaning up stale peers
+/// on large, ACL-enabled deployments.
+///
+/// # Returns
+/// If error occurs it returns `WebError` object.
+#[utoipa::path(
+    post,
+    path = "/api/v1/device/delete",
+    request_body = DeleteDevices,
+    responses(
+        (status = 200, description = "Successfully deleted devices."),
+        (status = 401, description = "Unauthorized to delete devices, or step-up re-authentication is required.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 404, description = "One of the devices was not found.", body = ApiResponse, example = json!({"msg": "device id <id> not found"})),
+        (status = 500, description = "Cannot delete devices.", body = ApiResponse, example = json!({"msg": "Internal server error"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn delete_devices(
+    _can_manage_devices: CanManageDevices,
+    session: SessionInfo,
+    context: ApiRequestContext,
+    headers: HeaderMap,
+    State(appstate): State<AppState>,
+    Json(data): Json<DeleteDevices>,
+) -> ApiResult {
+    if let StepUpOutcome::Required(response) =
+        enforce_step_up_auth(&appstate.pool, &session, &headers).await?
+    {
+        return Ok(response);
+    }
+
+    let username = &session.user.username;
+    debug!(
+        "User {username} bulk deleting {} devices",
+        data.device_ids.len()
+    );
+
+    let mut transaction = appstate.pool.begin().await?;
+
+    let deleted_count = data.device_ids.len();
+    let mut affected_network_ids = HashSet::new();
+    let mut affected_user_ids = HashSet::new();
+    let mut device_deleted_events = Vec::new();
+
+    for device_id in data.device_ids {
+        let device = device_for_admin_or_self(&mut *transaction, &session, device_id).await?;
+
+        let device_info = DeviceInfo::from_device(&mut *transaction, device.clone()).await?;
+        let device_name = device.name.clone();
+        let device_type = device.device_type.clone();
+
+        ensure_can_manage_device_networks(
+            &appstate.pool,
+            session.user.id,
+            session.is_admin,
+            device.user_id == session.user.id,
+            &device_info
+                .network_info
+                .iter()
+                .map(|info| info.network_id)
+                .collect::<Vec<_>>(),
+        )
+        .await?;
+
+        affected_network_ids.extend(device_info.network_info.iter().map(|info| info.network_id));
+        if device_type == DeviceType::User {
+            affected_user_ids.insert(device.user_id);
+        }
+
+        appstate.ws_hub.broadcast(
+            WsMessage::new(WsUpdateType::DeviceUpdate, Some(device_id), None),
+            Some(device.user_id),
+        );
+
+        device.delete(&mut *transaction).await?;
+
+        match device_type {
+            DeviceType::User => {
+                let owner = device_info
+                    .device
+                    .get_owner(&mut *transaction)
+                    .await?
+                    .username;
+                appstate.emit_event(ApiEvent {
+                    context: context.clone(),
+                    event: ApiEventType::UserDeviceRemoved {
+                        device_name,
+                        owner,
+                        device_id,
+                    },
+                })?
+            }
+            DeviceType::Network => {
+                if let Some(network_info) = device_info.network_info.first() {
+                    let location =
+                        WireguardNetwork::find_by_id(&mut *transaction, network_info.network_id)
+                            .await?;
+                    if let Some(location) = location {
+                        appstate.emit_event(ApiEvent {
+                            context: context.clone(),
+                            event: ApiEventType::NetworkDeviceRemoved {
+                                device_id,
+                                device_name,
+                                location_id: location.id,
+                                location: location.name,
+                            },
+                        })?;
+                    } else {
+                        error!("Network device {device_name}({device_id}) is assigned to non-existent location {}", network_info.network_id);
+                    }
+                } else {
+                    error!("Network device {device_name}({device_id}) has no network assigned");
+                }
+            }
+        };
+
+        device_deleted_events.push(GatewayEvent::DeviceDeleted(device_info));
+    }
+
+    update_counts(&mut *transaction).await?;
+
+    // bump each affected user's device-list timestamp so a stale cached
+    // timestamp doesn't look fresh after their device set changed, same as
+    // the single-device delete/modify paths above.
+    for user_id in affected_user_ids {
+        validate_and_bump_device_list_timestamp(&mut transaction, user_id, None).await?;
+    }
+
+    // recompute each affected location's firewall config exactly once, instead of
+    // once per deleted device
+    let mut events = Vec::new();
+    for network_id in affected_network_ids {
+        DeviceListVersion::append_new_version(&mut transaction, network_id).await?;
+
+        if let Some(location) = WireguardNetwork::find_by_id(&mut *transaction, network_id).await?
+        {
+            if let Some(firewall_config) = location.try_get_firewall_config(&mut transaction).await?
+            {
+                debug!("Sending firewall config update for location {location} affected by bulk device deletion by user {username}");
+                events.push(GatewayEvent::FirewallConfigChanged(
+                    location.id,
+                    firewall_config,
+                ));
+            }
+        }
+    }
+    events.extend(device_deleted_events);
+
+    transaction.commit().await?;
+
+    appstate.send_multiple_wireguard_events(events);
+
+    info!("User {username} bulk deleted {deleted_count} devices");
+
     Ok(ApiResponse::default())
 }
 
@@ -1151,6 +2506,187 @@ pub(crate) async fn list_user_devices(
     })
 }
 
+/// List user devices with device-list version
+///
+/// Like [`list_user_devices`], but also returns the user's current
+/// `device_list_timestamp` so a client can tell whether its cached list is
+/// still current before sending a `timestamp` back on a future mutation.
+///
+/// # Returns
+/// Returns a `{ devices, timestamp }` object or `WebError` object if error occurs.
+#[utoipa::path(
+    get,
+    path = "/api/v1/device/user/{username}/list",
+    params(
+        ("username" = String, description = "Name of a user.")
+    ),
+    responses(
+        (status = 200, description = "List user devices with device-list version.", body = ApiResponse, example = json!(
+            {
+                "devices": [
+                    {
+                        "id": 0,
+                        "name": "name",
+                        "wireguard_pubkey": "wireguard_pubkey",
+                        "user_id": 0,
+                        "created": "2024-07-10T10:25:43.231Z"
+                    }
+                ],
+                "timestamp": "2024-07-10T10:25:43.231Z"
+            }
+        )),
+        (status = 401, description = "Unauthorized to list user devices.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 403, description = "You don't have permission to list user devices.", body = ApiResponse, example = json!({"msg": "Admin access required"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn list_user_devices_with_timestamp(
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Path(username): Path<String>,
+) -> ApiResult {
+    // only allow for admin or user themselves
+    if !session.is_admin && session.user.username != username {
+        warn!(
+            "User {} tried to list devices for user {username}, but is not an admin",
+            session.user.username
+        );
+        return Err(WebError::Forbidden("Admin access required".into()));
+    }
+    debug!("Listing devices with device-list version for user: {username}");
+    let devices = Device::all_for_username(&appstate.pool, &username).await?;
+    let timestamp = query_as!(
+        DeviceListTimestampRow,
+        "SELECT device_list_timestamp FROM \"user\" WHERE username = $1",
+        username
+    )
+    .fetch_optional(&appstate.pool)
+    .await?
+    .and_then(|row| row.device_list_timestamp);
+    info!("Listed {} devices for user: {username}", devices.len());
+
+    Ok(ApiResponse {
+        json: json!({"devices": devices, "timestamp": timestamp}),
+        status: StatusCode::OK,
+    })
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RequestDeviceCommand {
+    kind: DeviceCommandKind,
+}
+
+/// Push a remote command to a device
+///
+/// Queues the command for the device to drain on its next poll (see
+/// [`get_device_commands`]) and, for `RotatePresharedKey`, immediately
+/// rotates the device's preshared key on every network it belongs to.
+/// Translated into a `GatewayEvent` so an already-connected gateway can act
+/// on it right away.
+///
+/// # Returns
+/// Returns `DeviceCommand` object or `WebError` object if error occurs.
+#[utoipa::path(
+    post,
+    path = "/api/v1/device/{device_id}/command",
+    params(
+        ("device_id" = i64, description = "Id of the device to command.")
+    ),
+    request_body = RequestDeviceCommand,
+    responses(
+        (status = 201, description = "Command queued for the device.", body = DeviceCommand),
+        (status = 401, description = "Unauthorized to command a device.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 404, description = "Device not found.", body = ApiResponse, example = json!({"msg": "device id <id> not found"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn create_device_command(
+    _can_manage_devices: CanManageDevices,
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Path(device_id): Path<i64>,
+    Json(data): Json<RequestDeviceCommand>,
+) -> ApiResult {
+    debug!(
+        "User {} queuing {:?} command for device {device_id}",
+        session.user.username, data.kind
+    );
+    let device = device_for_admin_or_self(&appstate.pool, &session, device_id).await?;
+
+    let mut transaction = appstate.pool.begin().await?;
+    let command = DeviceCommand::new(device.id, data.kind)
+        .save(&mut *transaction)
+        .await?;
+
+    if let DeviceCommandKind::RotatePresharedKey = data.kind {
+        device.rotate_preshared_keys(&mut transaction).await?;
+    }
+
+    let device_info = DeviceInfo::from_device(&mut *transaction, device).await?;
+    transaction.commit().await?;
+
+    let event = match data.kind {
+        DeviceCommandKind::ForceReconfigure | DeviceCommandKind::Disconnect => {
+            GatewayEvent::DevicePeerReset(device_info)
+        }
+        DeviceCommandKind::RotatePresharedKey => GatewayEvent::DeviceModified(device_info),
+    };
+    appstate.send_wireguard_event(event);
+
+    info!(
+        "User {} queued {:?} command for device {device_id}",
+        session.user.username, command.kind
+    );
+
+    Ok(ApiResponse {
+        json: json!(command),
+        status: StatusCode::CREATED,
+    })
+}
+
+/// Drain pending remote commands for a device
+///
+/// Returns and acknowledges (clearing) every command still pending for the
+/// device.
+///
+/// # Returns
+/// Returns a list of `DeviceCommand` objects or `WebError` object if error occurs.
+#[utoipa::path(
+    get,
+    path = "/api/v1/device/{device_id}/commands",
+    params(
+        ("device_id" = i64, description = "Id of the device to drain commands for.")
+    ),
+    responses(
+        (status = 200, description = "Pending commands for the device.", body = [DeviceCommand]),
+        (status = 401, description = "Unauthorized to read commands for this device.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+        (status = 404, description = "Device not found.", body = ApiResponse, example = json!({"msg": "device id <id> not found"}))
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn get_device_commands(
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Path(device_id): Path<i64>,
+) -> ApiResult {
+    let device = device_for_admin_or_self(&appstate.pool, &session, device_id).await?;
+    let commands = DeviceCommand::drain_pending(&appstate.pool, device.id).await?;
+
+    Ok(ApiResponse {
+        json: json!(commands),
+        status: StatusCode::OK,
+    })
+}
+
 pub(crate) async fn download_config(
     session: SessionInfo,
     State(appstate): State<AppState>,
@@ -1163,7 +2699,16 @@ pub(crate) async fn download_config(
         WireguardNetworkDevice::find(&appstate.pool, device_id, network_id).await?;
     if let Some(wireguard_network_device) = wireguard_network_device {
         info!("Created config for device {}({device_id})", device.name);
-        Ok(Device::create_config(&network, &wireguard_network_device))
+        let mesh_peers = if network.mesh_enabled {
+            Device::mesh_peers(&appstate.pool, network.id, device.id).await?
+        } else {
+            Vec::new()
+        };
+        Ok(Device::create_config(
+            &network,
+            &wireguard_network_device,
+            &mesh_peers,
+        ))
     } else {
         error!(
             "Failed to create config, no IP address found for device: {}({})",
@@ -1243,7 +2788,7 @@ pub struct DevicesStatsResponse {
 /// # Returns
 /// Returns an `DevicesStatsResponse` for requested network and time period
 pub(crate) async fn devices_stats(
-    _role: AdminRole,
+    _role: ViewNetworkStatsRole,
     State(appstate): State<AppState>,
     Path(network_id): Path<i64>,
     Query(query_from): Query<QueryFrom>,
@@ -1280,7 +2825,7 @@ pub(crate) async fn devices_stats(
 /// # Returns
 /// Returns an `WireguardNetworkStats` based on requested network and time period
 pub(crate) async fn network_stats(
-    _role: AdminRole,
+    _role: ViewNetworkStatsRole,
     State(appstate): State<AppState>,
     Path(network_id): Path<i64>,
     Query(query_from): Query<QueryFrom>,
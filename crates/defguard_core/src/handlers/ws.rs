@@ -0,0 +1,72 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    response::Response,
+};
+use tokio::sync::mpsc;
+
+use crate::{appstate::AppState, auth::SessionInfo};
+
+/// Upgrades the connection to a WebSocket and starts forwarding state-change
+/// events relevant to the authenticated session. Events are authorization
+/// filtered server-side by [`crate::ws::WsHub`] based on the session's user
+/// id and admin status, so a client only ever hears about what it's allowed
+/// to see.
+#[utoipa::path(
+    get,
+    path = "/api/v1/ws",
+    responses(
+        (status = 101, description = "Switching protocols to WebSocket."),
+        (status = 401, description = "Unauthorized.", body = ApiResponse, example = json!({"msg": "Session is required"})),
+    ),
+    security(
+        ("cookie" = []),
+        ("api_token" = [])
+    )
+)]
+pub(crate) async fn ws_handler(
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, appstate, session))
+}
+
+async fn handle_socket(mut socket: WebSocket, appstate: AppState, session: SessionInfo) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let connection_id = appstate
+        .ws_hub
+        .register(session.user.id, session.is_admin, tx);
+    info!(
+        "User {} opened a WebSocket connection ({connection_id})",
+        session.user.username
+    );
+
+    loop {
+        tokio::select! {
+            message = rx.recv() => {
+                let Some(message) = message else { break; };
+                let Ok(payload) = serde_json::to_string(&message) else { continue; };
+                if socket.send(Message::Text(payload.into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Err(_)) => break,
+                    // Clients don't send anything meaningful other than pings/closes.
+                    _ => continue,
+                }
+            }
+        }
+    }
+
+    appstate.ws_hub.unregister(connection_id);
+    debug!(
+        "WebSocket connection {connection_id} for user {} closed",
+        session.user.username
+    );
+}
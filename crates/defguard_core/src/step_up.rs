@@ -0,0 +1,141 @@
+//! UIAA-style step-up re-authentication for destructive device operations.
+//!
+//! `delete_device`, `delete_devices` and `modify_device` would otherwise be
+//! guarded only by an active session, so a hijacked cookie could silently
+//! remove peers or swap a device's pubkey. Before letting one of those
+//! requests through, we require the caller to have separately completed
+//! every flow in `server_config().step_up_auth_flows` (e.g. password,
+//! TOTP) against a short-lived [`StepUpSession`], referenced by the
+//! `X-Step-Up-Session-Id` header. Flows are completed one at a time via
+//! [`complete_step_up`] before the original request is resubmitted with
+//! that header set.
+
+use axum::{
+    extract::{Json, Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{
+    appstate::AppState,
+    auth::SessionInfo,
+    db::models::step_up_session::{AuthFlow, StepUpSession},
+    error::WebError,
+    handlers::{ApiResponse, ApiResult},
+    server_config,
+};
+
+const STEP_UP_SESSION_HEADER: &str = "x-step-up-session-id";
+
+/// Outcome of checking a request's step-up re-auth status.
+pub(crate) enum StepUpOutcome {
+    /// Every required flow has been completed recently enough; proceed.
+    Satisfied,
+    /// Not satisfied: the handler should return this response as-is.
+    Required(ApiResponse),
+}
+
+/// Checks whether `headers` carries a [`StepUpSession`] id that has already
+/// completed every flow required by this deployment, issuing a fresh
+/// challenge (as a `401` listing the required flows and a new
+/// `session_id`) if not.
+pub(crate) async fn enforce_step_up_auth(
+    pool: &PgPool,
+    session: &SessionInfo,
+    headers: &HeaderMap,
+) -> Result<StepUpOutcome, WebError> {
+    let required = &server_config().step_up_auth_flows;
+    if required.is_empty() {
+        return Ok(StepUpOutcome::Satisfied);
+    }
+
+    let provided_session_id = headers
+        .get(STEP_UP_SESSION_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    if let Some(session_id) = provided_session_id {
+        if let Some(step_up) = StepUpSession::find_by_session_id(pool, session_id).await? {
+            if step_up.user_id == session.user.id && step_up.satisfies(required) {
+                return Ok(StepUpOutcome::Satisfied);
+            }
+        }
+    }
+
+    // A hijacked session cookie can't reset its attempt budget by simply
+    // discarding the session id: the lockout is keyed on the user, not the
+    // session, so minting a fresh challenge doesn't grant fresh guesses.
+    if StepUpSession::user_locked_out(pool, session.user.id).await? {
+        return Err(WebError::TooManyLoginAttempts(format!(
+            "user {} is locked out of step-up re-authentication after too many failed attempts",
+            session.user.id
+        )));
+    }
+
+    let challenge = StepUpSession::new(session.user.id).save(pool).await?;
+    Ok(StepUpOutcome::Required(ApiResponse {
+        json: json!({
+            "msg": "Step-up re-authentication required",
+            "session_id": challenge.session_id,
+            "flows": required,
+        }),
+        status: StatusCode::UNAUTHORIZED,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompleteStepUp {
+    flow: AuthFlow,
+    /// The user's password, or a current TOTP code, depending on `flow`.
+    proof: String,
+}
+
+/// Complete one stage of a step-up re-authentication challenge.
+///
+/// # Returns
+/// Returns an empty `ApiResponse` object or `WebError` object if error occurs.
+pub(crate) async fn complete_step_up(
+    session: SessionInfo,
+    State(appstate): State<AppState>,
+    Path(session_id): Path<String>,
+    Json(data): Json<CompleteStepUp>,
+) -> ApiResult {
+    let Some(mut step_up) =
+        StepUpSession::find_by_session_id(&appstate.pool, &session_id).await?
+    else {
+        return Err(WebError::ObjectNotFound(format!(
+            "step-up session {session_id} not found"
+        )));
+    };
+
+    if step_up.user_id != session.user.id {
+        return Err(WebError::Forbidden(
+            "Step-up session belongs to a different user".into(),
+        ));
+    }
+
+    if step_up.is_expired() {
+        return Err(WebError::Http(StatusCode::GONE));
+    }
+
+    if step_up.is_locked_out() {
+        return Err(WebError::TooManyLoginAttempts(format!(
+            "step-up session {session_id} is locked out after too many failed attempts"
+        )));
+    }
+
+    let verified = match data.flow {
+        AuthFlow::Password => session.user.verify_password(&data.proof).is_ok(),
+        AuthFlow::Totp => session.user.totp_enabled && session.user.verify_totp_code(&data.proof),
+    };
+    if !verified {
+        step_up.record_failed_attempt(&appstate.pool).await?;
+        return Err(WebError::Http(StatusCode::UNAUTHORIZED));
+    }
+
+    step_up
+        .mark_completed(&appstate.pool, data.flow)
+        .await?;
+
+    Ok(ApiResponse::default())
+}
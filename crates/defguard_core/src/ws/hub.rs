@@ -0,0 +1,96 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
+
+use super::message::{WsMessage, WsUpdateType};
+use crate::db::Id;
+
+struct Subscriber {
+    user_id: Id,
+    is_admin: bool,
+    sender: UnboundedSender<WsMessage>,
+}
+
+/// Fan-out hub for WebSocket-connected clients, held in [`crate::appstate::AppState`].
+///
+/// Each connection registers itself with the authorization context it was
+/// established with (user id, admin status), so broadcast events are
+/// filtered server-side instead of relying on the client to discard what it
+/// shouldn't see.
+#[derive(Clone, Default)]
+pub struct WsHub {
+    subscribers: Arc<Mutex<Vec<(Uuid, Subscriber)>>>,
+}
+
+impl WsHub {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new connection and returns its connection UUID, used both
+    /// to key the subscription and so clients can tag messages they
+    /// originate (letting them ignore their own echoes).
+    pub fn register(&self, user_id: Id, is_admin: bool, sender: UnboundedSender<WsMessage>) -> Uuid {
+        let connection_id = Uuid::new_v4();
+        self.subscribers.lock().unwrap().push((
+            connection_id,
+            Subscriber {
+                user_id,
+                is_admin,
+                sender,
+            },
+        ));
+        connection_id
+    }
+
+    pub fn unregister(&self, connection_id: Uuid) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|(id, _)| *id != connection_id);
+    }
+
+    /// Returns `true` if `subscriber` is authorized to see an event about
+    /// `object_owner_id` (when one is known). Admins see everything; regular
+    /// users only see events about themselves.
+    fn is_authorized(subscriber: &Subscriber, object_owner_id: Option<Id>) -> bool {
+        subscriber.is_admin
+            || object_owner_id.is_none()
+            || object_owner_id == Some(subscriber.user_id)
+    }
+
+    /// Broadcasts `message` to every subscriber authorized to see it.
+    /// `object_owner_id` scopes the event to a specific user when
+    /// applicable (e.g. a single user's device changed); `None` means the
+    /// event is relevant to everyone (e.g. a VPN location config change).
+    pub fn broadcast(&self, message: WsMessage, object_owner_id: Option<Id>) {
+        let mut stale = Vec::new();
+        for (connection_id, subscriber) in self.subscribers.lock().unwrap().iter() {
+            if !Self::is_authorized(subscriber, object_owner_id) {
+                continue;
+            }
+            // A connection that originated this change ignores its own echo.
+            if message.origin == Some(*connection_id) {
+                continue;
+            }
+            if subscriber.sender.send(message.clone()).is_err() {
+                stale.push(*connection_id);
+            }
+        }
+        if !stale.is_empty() {
+            self.subscribers
+                .lock()
+                .unwrap()
+                .retain(|(id, _)| !stale.contains(id));
+        }
+    }
+
+    pub fn broadcast_logout(&self, user_id: Id) {
+        self.broadcast(
+            WsMessage::new(WsUpdateType::LogOut, Some(user_id), None),
+            Some(user_id),
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use uuid::Uuid;
+
+use crate::db::Id;
+
+/// The kind of state change a [`WsMessage`] is reporting.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WsUpdateType {
+    UserUpdate,
+    DeviceUpdate,
+    AclUpdate,
+    SyncKeys,
+    LogOut,
+}
+
+/// A single typed event broadcast to connected WebSocket clients.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WsMessage {
+    pub update_type: WsUpdateType,
+    /// The id of the entity that changed (user, device, ACL rule, ...),
+    /// when applicable.
+    pub object_id: Option<Id>,
+    /// The connection UUID of whoever triggered this change, so that
+    /// client can recognize and ignore updates it caused itself.
+    pub origin: Option<Uuid>,
+}
+
+impl WsMessage {
+    #[must_use]
+    pub fn new(update_type: WsUpdateType, object_id: Option<Id>, origin: Option<Uuid>) -> Self {
+        Self {
+            update_type,
+            object_id,
+            origin,
+        }
+    }
+}
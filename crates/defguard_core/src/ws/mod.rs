@@ -0,0 +1,13 @@
+//! Real-time event push to connected web/enrolled clients over WebSocket.
+//!
+//! Reuses the same "reconfiguration notification" idea already used by
+//! [`crate::enterprise::activity_log_stream`]: rather than polling, a typed
+//! event is pushed out as soon as state changes, and each connection decides
+//! for itself (based on the authorization context it registered with)
+//! whether the event is relevant.
+
+pub mod hub;
+pub mod message;
+
+pub use hub::WsHub;
+pub use message::{WsMessage, WsUpdateType};